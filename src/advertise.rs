@@ -0,0 +1,183 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use thiserror::Error;
+
+use crate::{InterfacePreference, NodeConfig};
+
+#[derive(Debug, Error)]
+pub enum AdvertiseError {
+	#[error("advertise address `{0}` is unspecified")]
+	Unspecified(SocketAddr),
+	#[error("advertise address `{0}` is a loopback address, but seed `{1}` is not")]
+	Loopback(SocketAddr, SocketAddr),
+	#[error("no usable interface address found to advertise")]
+	NoAddress,
+	#[error("cannot list network interfaces: {0}")]
+	Interfaces(#[from] io::Error),
+}
+
+/// Returns the address which is advertised to other nodes.
+///
+/// The address is chosen in the following order:
+/// 1. [NodeConfig::advertise_addr] if set.
+/// 2. [NodeConfig::bind_addr] if it is not a wildcard address.
+/// 3. The first address of a network interface matching [NodeConfig::advertise_interface], or the first
+///    private address if no preference is set. The port of [NodeConfig::bind_addr] is used.
+///
+/// Fails if the chosen address is unspecified or a loopback address while one of the `seeds` is not.
+pub(crate) fn advertise_addr(
+	config: &NodeConfig,
+	seeds: &[SocketAddr],
+) -> Result<SocketAddr, AdvertiseError> {
+	let addr = match config.advertise_addr {
+		Some(addr) => addr,
+		None if !config.bind_addr.ip().is_unspecified() => config.bind_addr,
+		None => {
+			let interfaces = if_addrs::get_if_addrs()?
+				.into_iter()
+				.map(|i| (i.name, i.addr.ip()))
+				.collect::<Vec<_>>();
+
+			let ip = select(
+				&interfaces,
+				config.bind_addr.ip(),
+				config.advertise_interface.as_ref(),
+			)
+			.ok_or(AdvertiseError::NoAddress)?;
+
+			SocketAddr::new(ip, config.bind_addr.port())
+		}
+	};
+
+	validate(addr, seeds)?;
+	Ok(addr)
+}
+
+fn validate(addr: SocketAddr, seeds: &[SocketAddr]) -> Result<(), AdvertiseError> {
+	if addr.ip().is_unspecified() {
+		return Err(AdvertiseError::Unspecified(addr));
+	}
+
+	if addr.ip().is_loopback() {
+		if let Some(seed) = seeds.iter().find(|s| !s.ip().is_loopback()) {
+			return Err(AdvertiseError::Loopback(addr, *seed));
+		}
+	}
+
+	Ok(())
+}
+
+/// Selects an interface address of the same family as `bind`.
+fn select(
+	interfaces: &[(String, IpAddr)],
+	bind: IpAddr,
+	preference: Option<&InterfacePreference>,
+) -> Option<IpAddr> {
+	let mut candidates = interfaces
+		.iter()
+		.filter(|(_, ip)| ip.is_ipv4() == bind.is_ipv4() && !ip.is_loopback());
+
+	let found = match preference {
+		Some(InterfacePreference::Name(name)) => candidates.find(|(n, _)| n == name),
+		Some(InterfacePreference::Network(net, prefix)) => {
+			candidates.find(|(_, ip)| in_network(*ip, *net, *prefix))
+		}
+		None => candidates.find(|(_, ip)| is_private(*ip)),
+	};
+
+	found.map(|(_, ip)| *ip)
+}
+
+fn is_private(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => ip.is_private(),
+		IpAddr::V6(ip) => ip.segments()[0] & 0xfe00 == 0xfc00, // unique local address
+	}
+}
+
+fn in_network(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+	match (ip, net) {
+		(IpAddr::V4(ip), IpAddr::V4(net)) => {
+			let mask = u32::MAX
+				.checked_shl(32 - u32::from(prefix.min(32)))
+				.unwrap_or(0);
+			u32::from(ip) & mask == u32::from(net) & mask
+		}
+		(IpAddr::V6(ip), IpAddr::V6(net)) => {
+			let mask = u128::MAX
+				.checked_shl(128 - u32::from(prefix.min(128)))
+				.unwrap_or(0);
+			u128::from(ip) & mask == u128::from(net) & mask
+		}
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ip(s: &str) -> IpAddr {
+		s.parse().unwrap()
+	}
+
+	fn addr(s: &str) -> SocketAddr {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn select_interface() {
+		let interfaces = vec![
+			("lo".to_string(), ip("127.0.0.1")),
+			("eth0".to_string(), ip("203.0.113.7")),
+			("eth1".to_string(), ip("10.1.2.3")),
+			("eth1".to_string(), ip("fd00::3")),
+			("eth2".to_string(), ip("192.168.0.9")),
+		];
+
+		let v4 = ip("0.0.0.0");
+		let v6 = ip("::");
+
+		assert_eq!(select(&interfaces, v4, None), Some(ip("10.1.2.3")));
+		assert_eq!(select(&interfaces, v6, None), Some(ip("fd00::3")));
+
+		let name = InterfacePreference::Name("eth0".into());
+		assert_eq!(
+			select(&interfaces, v4, Some(&name)),
+			Some(ip("203.0.113.7"))
+		);
+
+		let net = InterfacePreference::Network(ip("192.168.0.0"), 16);
+		assert_eq!(select(&interfaces, v4, Some(&net)), Some(ip("192.168.0.9")));
+
+		let missing = InterfacePreference::Name("lo".into());
+		assert_eq!(select(&interfaces, v4, Some(&missing)), None);
+	}
+
+	#[test]
+	fn validate_addr() {
+		let local = [addr("127.0.0.1:2")];
+		let remote = [addr("127.0.0.1:2"), addr("10.0.0.1:1")];
+
+		assert!(validate(addr("10.0.0.2:1"), &remote).is_ok());
+		assert!(validate(addr("127.0.0.1:1"), &local).is_ok());
+		assert!(matches!(
+			validate(addr("127.0.0.1:1"), &remote),
+			Err(AdvertiseError::Loopback(_, seed)) if seed == remote[1]
+		));
+		assert!(matches!(
+			validate(addr("0.0.0.0:1"), &local),
+			Err(AdvertiseError::Unspecified(_))
+		));
+	}
+
+	#[test]
+	fn network() {
+		assert!(in_network(ip("10.1.2.3"), ip("10.0.0.0"), 8));
+		assert!(!in_network(ip("11.1.2.3"), ip("10.0.0.0"), 8));
+		assert!(in_network(ip("11.1.2.3"), ip("10.0.0.0"), 0));
+		assert!(in_network(ip("fd00::1"), ip("fc00::"), 7));
+		assert!(!in_network(ip("fd00::1"), ip("10.0.0.0"), 0));
+	}
+}
@@ -1,5 +1,61 @@
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use tokio::time::Instant;
+
+use crate::clock::{Clock, TokioClock};
+use crate::AwarenessConfig;
+
+/// The reason why the awareness score changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthChangeReason {
+	/// An indirect ping received fewer `nacks` than expected.
+	MissedNack,
+	/// This node had to refute a suspicion about itself.
+	Refutation,
+	/// A probe failed.
+	FailedProbe,
+	/// A probe succeeded.
+	SuccessfulProbe,
+}
+
+/// An event which changes the awareness score as specified by the *Lifeguard*-paper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HealthEvent {
+	/// A direct or indirect probe has been acked.
+	ProbeSucceeded,
+	/// A probe failed. `expected_nacks` is the amount of nodes which were asked to probe the target
+	/// indirectly and `nacks` is the amount of them which sent a `nack` back.
+	ProbeFailed { expected_nacks: usize, nacks: usize },
+	/// This node had to refute a suspicion about itself.
+	Refuted,
+}
+
+/// A single change of the awareness score.
+#[derive(Debug, Clone)]
+pub struct HealthChange {
+	/// The time of the change, read from the [Clock] of the node.
+	pub at: Instant,
+	/// The score after the change.
+	pub score: NonZeroU32,
+	/// The reason for the change.
+	pub reason: HealthChangeReason,
+}
+
+/// A snapshot of the local health of a node.
+///
+/// A score of `1` means the node considers itself healthy, a score of `max` means it considers itself degraded.
+#[derive(Debug, Clone)]
+pub struct Health {
+	/// The current awareness score.
+	pub score: NonZeroU32,
+	/// The max awareness score.
+	pub max: NonZeroU32,
+	/// The most recent changes of the score, oldest first.
+	pub history: Vec<HealthChange>,
+}
 
 /// A local health awareness component which assumes values in the range of `[1, max]`.
 /// The counter always starts at `1`.
@@ -9,44 +65,129 @@ use std::num::NonZeroU32;
 ///	This change was made because the awareness score is only used in a context where it must be at least `1`.
 /// The component specified in the paper increments the score by `1` for each usecase (multiplier for the ping interval and timeout).
 /// To avoid this unneeded addition, the lower bound was raised to `1` and the upper bound was made inclusive.
+///
+/// The last `history_len` changes of the score are kept together with their reason.
 pub(crate) struct Awareness {
 	max: u32,
 	score: u32,
+
+	history: VecDeque<HealthChange>,
+	history_len: usize,
+	clock: Arc<dyn Clock>,
 }
 
 impl Default for Awareness {
-	/// Creates a new [Awareness]-counter with `max == 9`.
+	/// Creates a new [Awareness]-counter with `max == 9` which keeps the last `32` changes.
 	fn default() -> Self {
-		Self::new(NonZeroU32::new(9).unwrap())
+		Self::new(NonZeroU32::new(9).unwrap(), 32)
 	}
 }
 
 impl Awareness {
-	/// Creates a new [Awareness]-counter with a range of `[1, max]`.
+	/// Creates a new [Awareness]-counter with a range of `[1, max]` which keeps the last `history_len` changes.
 	/// The counter starts at `1`.
-	pub(crate) fn new(max: NonZeroU32) -> Self {
+	pub(crate) fn new(max: NonZeroU32, history_len: usize) -> Self {
 		Self {
 			max: max.into(),
 			score: 1,
+			history: VecDeque::with_capacity(history_len),
+			history_len,
+			clock: Arc::new(TokioClock),
 		}
 	}
 
+	/// Creates a new [Awareness]-counter from the given config.
+	pub(crate) fn from_config(config: &AwarenessConfig) -> Self {
+		Self::new(config.max, config.history_len)
+	}
+
+	/// Reads the time of each change from `clock` instead of the [TokioClock].
+	pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+		self.clock = clock;
+		self
+	}
+
 	/// Increments the `score`.
-	pub(crate) fn increment(&mut self) -> NonZeroU32 {
+	pub(crate) fn increment(&mut self, reason: HealthChangeReason) -> NonZeroU32 {
 		if self.score < self.max {
 			self.score += 1;
+			self.record(reason);
 		}
 		self.score()
 	}
 
 	/// Decrements the `score`.
-	pub(crate) fn decrement(&mut self) -> NonZeroU32 {
+	pub(crate) fn decrement(&mut self, reason: HealthChangeReason) -> NonZeroU32 {
 		if self.score > 1 {
 			self.score -= 1;
+			self.record(reason);
 		}
 		self.score()
 	}
 
+	/// Applies a [HealthEvent] and returns [Some] new score if the score changed.
+	///
+	/// A failed probe without indirect probes raises the score by `1`. Otherwise the score is raised by
+	/// the amount of missing `nacks`, since the target failing is no sign of a degraded node as long as
+	/// every other node answers.
+	pub(crate) fn apply(&mut self, event: HealthEvent) -> Option<NonZeroU32> {
+		let score = self.score;
+
+		match event {
+			HealthEvent::ProbeSucceeded => {
+				self.decrement(HealthChangeReason::SuccessfulProbe);
+			}
+			HealthEvent::ProbeFailed {
+				expected_nacks: 0, ..
+			} => {
+				self.increment(HealthChangeReason::FailedProbe);
+			}
+			HealthEvent::ProbeFailed {
+				expected_nacks,
+				nacks,
+			} => {
+				for _ in nacks..expected_nacks {
+					self.increment(HealthChangeReason::MissedNack);
+				}
+			}
+			HealthEvent::Refuted => {
+				self.increment(HealthChangeReason::Refutation);
+			}
+		}
+
+		if self.score == score {
+			None
+		} else {
+			Some(self.score())
+		}
+	}
+
+	/// Appends the current score to the history. Drops the oldest entry if the history is full.
+	fn record(&mut self, reason: HealthChangeReason) {
+		if self.history_len == 0 {
+			return;
+		}
+
+		if self.history.len() == self.history_len {
+			self.history.pop_front();
+		}
+
+		self.history.push_back(HealthChange {
+			at: self.clock.now(),
+			score: self.score(),
+			reason,
+		});
+	}
+
+	/// Returns a [Health] snapshot containing the current score, the max score and the history.
+	pub(crate) fn health(&self) -> Health {
+		Health {
+			score: self.score(),
+			max: self.max(),
+			history: self.history.iter().cloned().collect(),
+		}
+	}
+
 	/// Returns the current awareness score.
 	#[inline]
 	pub fn score(&self) -> NonZeroU32 {
@@ -62,7 +203,10 @@ impl Awareness {
 
 #[cfg(test)]
 mod tests {
+	use std::time::Duration;
+
 	use super::*;
+	use crate::clock::ManualClock;
 
 	#[test]
 	fn awareness() {
@@ -88,13 +232,89 @@ mod tests {
 		for (rounds, result) in cases {
 			for _ in 0..rounds.abs() {
 				if rounds.is_negative() {
-					a.decrement();
+					a.decrement(HealthChangeReason::SuccessfulProbe);
 				} else {
-					a.increment();
+					a.increment(HealthChangeReason::FailedProbe);
 				};
 			}
 
 			assert_eq!(a.score().get(), result);
 		}
 	}
+
+	#[test]
+	fn lifeguard_rules() {
+		let mut a = Awareness::new(NonZeroU32::new(8).unwrap(), 16);
+
+		let failed = |expected_nacks, nacks| HealthEvent::ProbeFailed {
+			expected_nacks,
+			nacks,
+		};
+
+		let cases = vec![
+			(HealthEvent::ProbeSucceeded, None),
+			(failed(0, 0), Some(2)),
+			(failed(3, 3), None),
+			(failed(3, 1), Some(4)),
+			(HealthEvent::Refuted, Some(5)),
+			(HealthEvent::ProbeSucceeded, Some(4)),
+			(failed(10, 0), Some(8)),
+		];
+
+		for (event, expected) in cases {
+			assert_eq!(a.apply(event).map(NonZeroU32::get), expected, "{:?}", event);
+		}
+
+		let reasons: Vec<_> = a.health().history.iter().map(|c| c.reason).collect();
+		assert_eq!(
+			reasons,
+			vec![
+				HealthChangeReason::FailedProbe,
+				HealthChangeReason::MissedNack,
+				HealthChangeReason::MissedNack,
+				HealthChangeReason::Refutation,
+				HealthChangeReason::SuccessfulProbe,
+				HealthChangeReason::MissedNack,
+				HealthChangeReason::MissedNack,
+				HealthChangeReason::MissedNack,
+				HealthChangeReason::MissedNack,
+			]
+		);
+	}
+
+	#[test]
+	fn health_history() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let start = clock.now();
+		let mut a = Awareness::new(NonZeroU32::new(3).unwrap(), 2).with_clock(clock.clone());
+
+		a.decrement(HealthChangeReason::SuccessfulProbe); // unchanged, not recorded
+		a.increment(HealthChangeReason::FailedProbe);
+		a.increment(HealthChangeReason::MissedNack);
+		a.increment(HealthChangeReason::Refutation); // unchanged, not recorded
+		clock.advance(Duration::from_secs(1));
+		a.decrement(HealthChangeReason::SuccessfulProbe);
+
+		let health = a.health();
+		assert_eq!(health.score.get(), 2);
+		assert_eq!(health.max.get(), 3);
+
+		let history: Vec<_> = health
+			.history
+			.iter()
+			.map(|c| (c.at - start, c.score.get(), c.reason))
+			.collect();
+
+		assert_eq!(
+			history,
+			vec![
+				(Duration::ZERO, 3, HealthChangeReason::MissedNack),
+				(
+					Duration::from_secs(1),
+					2,
+					HealthChangeReason::SuccessfulProbe
+				)
+			]
+		);
+	}
 }
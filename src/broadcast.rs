@@ -0,0 +1,309 @@
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use crate::metrics::Metrics;
+
+/// The priority of a [Broadcast]. Broadcasts with a higher priority are sent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+	/// Routine `alive` updates and user payloads.
+	Routine,
+	/// `suspect` and `dead` messages.
+	Failure,
+	/// The refutation of a suspicion about the local node.
+	Refutation,
+}
+
+impl Priority {
+	fn is_failure_related(self) -> bool {
+		self != Priority::Routine
+	}
+}
+
+#[derive(Debug)]
+struct Broadcast {
+	/// The node the broadcast is about. A newer broadcast about the same node replaces the old one.
+	/// User payloads are not about a node and never replaced.
+	node: Option<SocketAddr>,
+	msg: Box<[u8]>,
+	priority: Priority,
+	transmits: u32,
+	/// Increases with each queued broadcast to prefer newer broadcasts.
+	id: u64,
+}
+
+/// A queue of broadcasts which get piggybacked on outgoing packets. Each broadcast is sent a limited
+/// number of times, which scales with the cluster size.
+///
+/// Failure-related broadcasts ([Priority::Failure] and [Priority::Refutation]) are guaranteed a share of
+/// each packet and are placed before routine broadcasts. Space which is not used by one class is
+/// given to the other. Within a class, broadcasts with a higher [Priority] come first, then those which
+/// have been sent less often, then newer ones. Failure-related broadcasts which are larger than the
+/// guaranteed share are placed before routine broadcasts as long as they fit into the packet. A
+/// refutation is therefore never starved by routine churn.
+#[derive(Debug)]
+pub(crate) struct BroadcastQueue {
+	queue: Vec<Broadcast>,
+	next_id: u64,
+
+	multiplier: NonZeroU32,
+	failure_share: f64,
+
+	metrics: Arc<Metrics>,
+}
+
+impl BroadcastQueue {
+	pub(crate) fn new(multiplier: NonZeroU32, failure_share: f64, metrics: Arc<Metrics>) -> Self {
+		Self {
+			queue: Vec::new(),
+			next_id: 0,
+			multiplier,
+			failure_share: failure_share.clamp(0.0, 1.0),
+			metrics,
+		}
+	}
+
+	/// Returns the amount of queued broadcasts.
+	#[inline]
+	pub(crate) fn len(&self) -> usize {
+		self.queue.len()
+	}
+
+	/// Queues a broadcast. A queued broadcast about the same `node` is replaced.
+	pub(crate) fn queue(&mut self, node: Option<SocketAddr>, msg: Box<[u8]>, priority: Priority) {
+		if node.is_some() {
+			self.queue.retain(|b| b.node != node);
+		}
+
+		self.queue.push(Broadcast {
+			node,
+			msg,
+			priority,
+			transmits: 0,
+			id: self.next_id,
+		});
+		self.next_id += 1;
+
+		self.update_depth();
+	}
+
+	/// Returns the broadcasts to send in a packet with `limit` free bytes. Each broadcast additionally
+	/// uses `overhead` bytes. Broadcasts which have been sent often enough for a cluster of `node_count`
+	/// nodes are removed.
+	pub(crate) fn get_broadcasts(
+		&mut self,
+		overhead: usize,
+		limit: usize,
+		node_count: usize,
+	) -> Vec<Box<[u8]>> {
+		let transmit_limit = self.transmit_limit(node_count);
+
+		self.queue.sort_by(|a, b| {
+			b.priority
+				.cmp(&a.priority)
+				.then(a.transmits.cmp(&b.transmits))
+				.then(b.id.cmp(&a.id))
+		});
+
+		let reserved = (limit as f64 * self.failure_share) as usize;
+		let mut selected = vec![false; self.queue.len()];
+
+		// failure-related broadcasts first, within their guaranteed share,
+		let mut used = self.select(&mut selected, true, overhead, reserved);
+		// then failure-related broadcasts which never fit into the share,
+		used += self.select_oversized(&mut selected, overhead, reserved, limit - used);
+		// then routine broadcasts,
+		used += self.select(&mut selected, false, overhead, limit - used);
+		// then failure-related broadcasts using the space left by routine broadcasts.
+		self.select(&mut selected, true, overhead, limit - used);
+
+		let mut result = Vec::new();
+		for (b, _) in self
+			.queue
+			.iter_mut()
+			.zip(selected.iter())
+			.filter(|(_, s)| **s)
+		{
+			b.transmits += 1;
+			result.push(b.msg.clone());
+		}
+
+		self.queue.retain(|b| b.transmits < transmit_limit);
+
+		self.update_depth();
+		result
+	}
+
+	/// Marks unselected broadcasts of the given class as selected while they fit into `limit`.
+	/// Returns the amount of used bytes.
+	fn select(&self, selected: &mut [bool], failure: bool, overhead: usize, limit: usize) -> usize {
+		let mut used = 0;
+
+		for (b, s) in self.queue.iter().zip(selected.iter_mut()) {
+			if *s || b.priority.is_failure_related() != failure {
+				continue;
+			}
+
+			let size = b.msg.len() + overhead;
+			if used + size <= limit {
+				used += size;
+				*s = true;
+			}
+		}
+
+		used
+	}
+
+	/// Marks unselected failure-related broadcasts which are larger than `reserved` as selected while
+	/// they fit into `limit`. Returns the amount of used bytes.
+	fn select_oversized(
+		&self,
+		selected: &mut [bool],
+		overhead: usize,
+		reserved: usize,
+		limit: usize,
+	) -> usize {
+		let mut used = 0;
+
+		for (b, s) in self.queue.iter().zip(selected.iter_mut()) {
+			if *s || !b.priority.is_failure_related() {
+				continue;
+			}
+
+			let size = b.msg.len() + overhead;
+			if size > reserved && used + size <= limit {
+				used += size;
+				*s = true;
+			}
+		}
+
+		used
+	}
+
+	fn transmit_limit(&self, node_count: usize) -> u32 {
+		let n = node_count as f64 + 1.0;
+		let scale = n.log10().ceil() as u32;
+
+		self.multiplier.get() * u32::max(scale, 1)
+	}
+
+	fn update_depth(&self) {
+		self.metrics
+			.broadcast_queue_depth
+			.set(self.queue.len() as u64);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn addr(port: u16) -> SocketAddr {
+		format!("127.0.0.1:{}", port).parse().unwrap()
+	}
+
+	fn queue() -> BroadcastQueue {
+		BroadcastQueue::new(
+			NonZeroU32::new(2).unwrap(),
+			0.5,
+			Arc::new(Metrics::default()),
+		)
+	}
+
+	#[test]
+	fn replaces_broadcasts_about_the_same_node() {
+		let mut q = queue();
+
+		q.queue(Some(addr(1)), vec![1].into(), Priority::Routine);
+		q.queue(Some(addr(1)), vec![2].into(), Priority::Failure);
+		q.queue(None, vec![3].into(), Priority::Routine);
+		q.queue(None, vec![4].into(), Priority::Routine);
+
+		assert_eq!(q.len(), 3);
+		assert_eq!(q.metrics.broadcast_queue_depth.get(), 3);
+	}
+
+	#[test]
+	fn transmit_limit() {
+		let mut q = queue();
+		q.queue(Some(addr(1)), vec![1].into(), Priority::Routine);
+
+		// 2 * ceil(log10(10 + 1)) = 4
+		for _ in 0..4 {
+			assert_eq!(q.get_broadcasts(0, 100, 10).len(), 1);
+		}
+		assert!(q.get_broadcasts(0, 100, 10).is_empty());
+		assert_eq!(q.len(), 0);
+	}
+
+	#[test]
+	fn refutation_is_never_starved() {
+		let mut q = queue();
+
+		for i in 0..100 {
+			q.queue(Some(addr(i)), vec![0; 40].into(), Priority::Routine);
+		}
+		for i in 100..110 {
+			q.queue(Some(addr(i)), vec![1; 40].into(), Priority::Failure);
+		}
+		q.queue(Some(addr(1000)), vec![2; 40].into(), Priority::Refutation);
+
+		for round in 0..4 {
+			let broadcasts = q.get_broadcasts(10, 200, 10);
+
+			assert_eq!(&*broadcasts[0], &[2; 40][..], "round {}", round);
+
+			let failures = broadcasts.iter().filter(|b| b[0] != 0).count();
+			let routine = broadcasts.len() - failures;
+			assert_eq!((failures, routine), (2, 2));
+
+			// more routine churn
+			for i in 0..10 {
+				q.queue(
+					Some(addr(2000 + round * 10 + i)),
+					vec![0; 40].into(),
+					Priority::Routine,
+				);
+			}
+		}
+
+		// the refutation reached its transmit limit
+		let broadcasts = q.get_broadcasts(10, 200, 10);
+		assert!(broadcasts.iter().all(|b| b[0] != 2));
+	}
+
+	#[test]
+	fn oversized_refutation_is_not_starved() {
+		let mut q = queue();
+
+		for i in 0..100 {
+			q.queue(Some(addr(i)), vec![0; 20].into(), Priority::Routine);
+		}
+		// larger than the reserved half of the packet.
+		q.queue(Some(addr(1000)), vec![2; 140].into(), Priority::Refutation);
+
+		for round in 0..4 {
+			let broadcasts = q.get_broadcasts(10, 200, 10);
+
+			assert_eq!(&*broadcasts[0], &[2; 140][..], "round {}", round);
+			assert_eq!(broadcasts.len(), 2);
+		}
+	}
+
+	#[test]
+	fn unused_space_is_shared() {
+		let mut q = queue();
+
+		for i in 0..10 {
+			q.queue(Some(addr(i)), vec![1; 40].into(), Priority::Failure);
+		}
+		assert_eq!(q.get_broadcasts(10, 200, 10).len(), 4);
+
+		let mut q = queue();
+		for i in 0..10 {
+			q.queue(Some(addr(i)), vec![0; 40].into(), Priority::Routine);
+		}
+		assert_eq!(q.get_broadcasts(10, 200, 10).len(), 4);
+	}
+}
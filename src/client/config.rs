@@ -1,11 +1,14 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::num::{NonZeroU32, NonZeroUsize};
 use std::ops::RangeBounds;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use tokio::runtime::Runtime;
 
-use super::EventHandler;
+use super::{EventHandler, SuspicionTimeout};
+use crate::{Clock, MessageType};
 
 pub trait Configs {
 	fn loopback() -> Self;
@@ -18,7 +21,11 @@ pub trait Configs {
 #[derive(Debug, Clone)]
 pub struct JoinConfig {
 	pub max_rounds: Option<NonZeroUsize>,
-	pub seed_addrs: Box<[SocketAddr]>,
+	/// The seeds to join, given as `host:port`, e.g. `10.0.0.1:7946` or `seeds.example.com:7946`.
+	///
+	/// Host names are resolved through the system resolver at the start of each join round and
+	/// every returned address is tried.
+	pub seed_addrs: Box<[String]>,
 }
 
 #[derive(Debug, Clone)]
@@ -32,6 +39,9 @@ pub struct SyncConfig {
 pub struct BroadcastConfig {
 	pub multiplier: NonZeroU32,
 	pub free_bytes: usize,
+	/// The share of each packet, between `0.0` and `1.0`, which is reserved for suspect and dead
+	/// messages and refutations. Unused space is given to routine broadcasts.
+	pub failure_share: f64,
 }
 #[derive(Debug, Clone)]
 pub struct SuspicionConfig {
@@ -40,9 +50,50 @@ pub struct SuspicionConfig {
 	pub k: NonZeroU32,
 }
 
+/// A token bucket limit.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+	/// The amount of messages per second. Must be positive.
+	pub rate: f64,
+	/// The max amount of messages which may be received at once.
+	pub burst: NonZeroU32,
+}
+
+/// Limits the rate of incoming messages per source address and message type.
+/// Message types without a limit are not limited.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+	/// The limit for pings.
+	pub ping: Option<RateLimit>,
+	/// The limit for ping requests.
+	pub ping_request: Option<RateLimit>,
+	/// The limit for suspect messages.
+	pub suspect: Option<RateLimit>,
+	/// The limit for every other message type.
+	pub other: Option<RateLimit>,
+	/// The max amount of ongoing ping requests per requesting node.
+	pub max_ping_requests: Option<NonZeroUsize>,
+}
+
+impl RateLimitConfig {
+	pub(crate) fn limit(&self, kind: MessageType) -> Option<&RateLimit> {
+		match kind {
+			MessageType::Ping => self.ping.as_ref(),
+			MessageType::PingRequest => self.ping_request.as_ref(),
+			MessageType::Suspect => self.suspect.as_ref(),
+			_ => self.other.as_ref(),
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct PingConfig {
 	pub indirect_checks: Option<NonZeroUsize>,
+	/// Pings a node additionally via TCP while the indirect pings are running.
+	///
+	/// A node which only answers the TCP ping is not suspected. This prevents firewalls which drop
+	/// UDP packets from getting nodes killed.
+	pub tcp_fallback: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -51,20 +102,83 @@ where
 	R: RangeBounds<usize>,
 {
 	pub node_range: R,
+	/// How long a dead node keeps receiving gossip after its death.
+	pub gossip_to_dead: Duration,
+}
+
+/// Configuration of the *Vivaldi* network coordinate system.
+#[derive(Debug, Clone)]
+pub struct CoordinateConfig {
+	/// The dimensionality of the coordinate system.
+	pub dimensionality: NonZeroUsize,
+	/// The max error of a coordinate. New coordinates start with this error.
+	pub error_max: f64,
+	/// Tuning factor which controls the max impact an observation can have on the error of a node's coordinate.
+	pub vivaldi_ce: f64,
+	/// Tuning factor which controls the max impact an observation can have on a node's coordinate.
+	pub vivaldi_cc: f64,
+	/// The amount of samples used to calculate the adjustment term. Set to `0` to disable adjustments.
+	pub adjustment_window_size: usize,
+	/// The min height of a coordinate.
+	pub height_min: Duration,
+	/// The amount of round-trip time samples per node whose median is used to filter out spikes.
+	pub latency_filter_size: NonZeroUsize,
+	/// Tuning factor for the force pulling coordinates towards the origin. Given in seconds.
+	pub gravity_rho: f64,
+}
+
+impl Default for CoordinateConfig {
+	fn default() -> Self {
+		Self {
+			dimensionality: NonZeroUsize::new(8).unwrap(),
+			error_max: 1.5,
+			vivaldi_ce: 0.25,
+			vivaldi_cc: 0.25,
+			adjustment_window_size: 20,
+			height_min: Duration::from_micros(10),
+			latency_filter_size: NonZeroUsize::new(3).unwrap(),
+			gravity_rho: 150.0,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct NodeConfig {
 	pub bind_addr: SocketAddr,
-	pub advertise_addr: SocketAddr,
+	/// The address advertised to other nodes.
+	///
+	/// If [None], the bind address is used. If the bind address is a wildcard address, an
+	/// interface address is chosen using [NodeConfig::advertise_interface].
+	pub advertise_addr: Option<SocketAddr>,
+	/// The interface to pick the advertise address from. The first private address is used if [None].
+	pub advertise_interface: Option<InterfacePreference>,
+	/// A label which is sent with every packet and push-pull header. Messages with a different label are rejected.
+	///
+	/// Use distinct labels to isolate clusters sharing the same hosts or subnets. The label must not be longer
+	/// than [MAX_LABEL_LEN](crate::MAX_LABEL_LEN) bytes.
+	pub label: Option<String>,
 
 	pub state: StateConfig,
 }
 
+/// Selects the network interface whose address is advertised.
+#[derive(Debug, Clone)]
+pub enum InterfacePreference {
+	/// Use the interface with the given name, e.g. `eth0`.
+	Name(String),
+	/// Use the first address within the given network, e.g. `10.0.0.0/8`.
+	Network(IpAddr, u8),
+}
+
 #[derive(Debug, Clone)]
 pub struct StateConfig {
 	pub incarnation: u64,
 	pub metadata: Option<Box<[u8]>>,
+	/// A directory to persist the incarnation number and the last known peers in.
+	///
+	/// If set, a restarted node continues above its previous incarnation number and uses the
+	/// last known peers as additional seeds.
+	pub state_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -77,13 +191,18 @@ pub struct IOConfig {
 
 #[derive(Debug, Clone)]
 pub struct AwarenessConfig {
-	max: NonZeroU32,
+	/// The max awareness score.
+	pub max: NonZeroU32,
+	/// The amount of awareness score changes kept for [Health](crate::Health) queries.
+	pub history_len: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct ReclaimConfig {
-	dead: Duration,
-	left: Duration,
+	/// How long a dead node is kept before it is removed.
+	pub dead: Duration,
+	/// How long a node which left is kept before it is removed.
+	pub left: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -91,8 +210,38 @@ pub struct SchedulerConfig {
 	pub ping: PingSchedulerConfig,
 	pub sync: SyncSchedulerConfig,
 	pub base_gossip_interval: Duration,
-	pub suspicion: SuspicionConfig,
+	/// The suspicion timeout strategy. Use a [SuspicionConfig] for *Lifeguard* timeouts.
+	pub suspicion: Arc<dyn SuspicionTimeout>,
 	pub reclaim: ReclaimConfig,
+	pub rejoin: RejoinConfig,
+	/// The [Clock] driving all intervals and timers. Uses [TokioClock](crate::TokioClock) if [None].
+	pub clock: Option<Arc<dyn Clock>>,
+	pub jitter: JitterConfig,
+}
+
+/// Random jitter applied to the ping, gossip and sync intervals, so nodes started at the same time
+/// do not send their messages in lockstep.
+#[derive(Debug, Clone, Copy)]
+pub struct JitterConfig {
+	/// The max deviation of each tick from the interval, as a fraction of the interval in `[0, 1]`.
+	/// Each tick fires after `interval * (1 ± factor)`. Set to `0` to disable the jitter.
+	pub factor: f64,
+	/// Fires the first tick after a random fraction of the interval instead of the full interval.
+	pub spread_first_tick: bool,
+}
+
+/// Configures how an isolated node rejoins the cluster.
+#[derive(Debug, Clone)]
+pub struct RejoinConfig {
+	/// How long a node without any peers waits before it rejoins using the seeds.
+	/// A node whose peers are all dead or left rejoins right away.
+	pub alone_threshold: Duration,
+	/// The delay before the second rejoin attempt. The delay doubles with each failed attempt.
+	pub min_backoff: Duration,
+	/// The max delay between rejoin attempts.
+	pub max_backoff: Duration,
+	/// The interval at which a random dead node is contacted to heal partitions.
+	pub reconnect_interval: Duration,
 }
 
 #[derive(Debug, Clone)]
@@ -105,6 +254,38 @@ pub struct SyncSchedulerConfig {
 pub struct PingSchedulerConfig {
 	pub base_interval: Duration,
 	pub base_timeout: Duration,
+	/// Derives the timeout of each probe from the [Rtt](crate::Rtt) of its target if set.
+	pub adaptive_timeout: Option<AdaptiveTimeoutConfig>,
+}
+
+/// Configures probe timeouts derived from the round-trip times of each node, like the retransmission
+/// timeout of TCP.
+///
+/// The timeout of a probe is `srtt + 4 * rttvar` scaled by the awareness score, but never shorter than
+/// [AdaptiveTimeoutConfig::min] and never longer than the timeout given by
+/// [PingSchedulerConfig::base_timeout]. Nodes without a round-trip time use the latter. The shorter
+/// timers of ping requests are bounded the same way, so no timer is shorter than the min timeout.
+/// Indirect probes always use the base timeout, since their round trip includes other nodes.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveTimeoutConfig {
+	/// The min timeout of a probe.
+	pub min: Duration,
+}
+
+/// Timing parameters which can be changed while a node is running.
+/// Parameters set to [None] are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct Reconfiguration {
+	/// The new base interval between pings.
+	pub ping_interval: Option<Duration>,
+	/// The new base timeout of pings.
+	pub ping_timeout: Option<Duration>,
+	/// The new base interval between gossip rounds.
+	pub gossip_interval: Option<Duration>,
+	/// The new base interval between syncs.
+	pub sync_interval: Option<Duration>,
+	/// The new suspicion timeout strategy. Use a [SuspicionConfig] for *Lifeguard* timeouts.
+	pub suspicion: Option<Arc<dyn SuspicionTimeout>>,
 }
 
 #[derive(Debug, Clone)]
@@ -121,6 +302,9 @@ where
 	pub sync: SyncConfig,
 	pub ping: PingConfig,
 	pub gossip: GossipConfig<R>,
+	/// Enables *Vivaldi* network coordinates if set.
+	pub coordinates: Option<CoordinateConfig>,
 	pub node: NodeConfig,
 	pub io: IOConfig,
+	pub rate_limit: RateLimitConfig,
 }
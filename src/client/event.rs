@@ -2,7 +2,7 @@ use std::io;
 use std::net::SocketAddr;
 use std::num::NonZeroU32;
 
-use crate::Node;
+use crate::{Node, Rtt};
 
 /// The cause why the node update event handler was invoked.
 pub enum Cause {
@@ -38,15 +38,28 @@ pub trait EventHandler {
 	/// Invoked if a sync failed.
 	fn sync_failed(&mut self, addr: &SocketAddr, err: io::Error) {}
 
+	/// Invoked if the snapshot could not be loaded from or stored in the
+	/// [state directory](crate::StateConfig::state_dir).
+	fn snapshot_failed(&mut self, err: io::Error) {}
+
 	/// Invoked when an `ack` has been received.
 	fn ack(&mut self, target: &SocketAddr) {}
 
 	/// Invoked when an indirect `ack` has been received.
 	fn indirect_ack(&mut self, target: &SocketAddr, from: &SocketAddr) {}
 
+	/// Invoked when the round-trip time estimate of a node has been updated after an `ack`.
+	fn rtt(&mut self, addr: &SocketAddr, rtt: &Rtt) {}
+
 	/// Invoked when a `nack` has been received.
 	fn nack(&mut self, target: &SocketAddr, from: &SocketAddr) {}
 
+	/// Invoked when a node could only be reached by the TCP fallback ping.
+	///
+	/// The node will not be suspected, but this usually means UDP packets between both nodes get dropped,
+	/// e.g. by a misconfigured firewall.
+	fn udp_unreachable(&mut self, addr: &SocketAddr) {}
+
 	/// Invoked when a ping has been received.
 	fn received_ping(&mut self, addr: &SocketAddr) {}
 
@@ -1,5 +1,7 @@
 mod config;
 mod event;
+mod timeout;
 
 pub use config::*;
 pub use event::*;
+pub use timeout::*;
@@ -0,0 +1,121 @@
+use std::fmt::Debug;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use super::SuspicionConfig;
+
+/// Computes how long a suspected node may refute a suspicion before it is declared dead.
+///
+/// The timeout is recomputed whenever a new suspector confirms the suspicion or the cluster size or
+/// probe interval changes. A timer always measures the timeout from the start of the suspicion,
+/// so a shrinking timeout may expire the suspicion right away.
+///
+/// [SuspicionConfig] implements the *Lifeguard* timeout and [FixedTimeout] a constant one.
+pub trait SuspicionTimeout: Debug + Send + Sync {
+	/// Returns the timeout for a suspicion in a cluster of `node_count` nodes probed every
+	/// `ping_interval`, which has been confirmed by `suspectors` distinct nodes, including the first one.
+	fn timeout(
+		&self,
+		node_count: NonZeroU32,
+		ping_interval: Duration,
+		suspectors: NonZeroU32,
+	) -> Duration;
+}
+
+/// The *Lifeguard* suspicion timeout.
+///
+/// The timeout starts at `beta * min` with `min = ping_interval * max(1, alpha * log10(node_count))`
+/// and decays logarithmically down to `min` once `k` suspectors confirmed the suspicion.
+impl SuspicionTimeout for SuspicionConfig {
+	fn timeout(
+		&self,
+		node_count: NonZeroU32,
+		ping_interval: Duration,
+		suspectors: NonZeroU32,
+	) -> Duration {
+		let node_count: f64 = node_count.get().into();
+		let scale = f64::max(1.0, self.alpha * node_count.log10());
+		let min = ping_interval.mul_f64(scale);
+
+		let max = min.mul_f64(self.beta);
+
+		lifeguard_timeout(self.k, min, max, suspectors)
+	}
+}
+
+fn lifeguard_timeout(k: NonZeroU32, min: Duration, max: Duration, c: NonZeroU32) -> Duration {
+	let min = min.as_secs_f64();
+	let max = max.as_secs_f64();
+
+	let c = c.get();
+	let k = k.get() + 1; // + 1 to ensure the divisor is never 0.
+
+	let c: f64 = c.into();
+	let k: f64 = k.into();
+
+	let frac = c.log10() / k.log10();
+	let f = max - (max - min) * frac;
+
+	let duration_secs = f64::max(min, f);
+	let duration_millis = (duration_secs * 1000f64).floor() as u64; // Round to millis.
+
+	Duration::from_millis(duration_millis)
+}
+
+/// A suspicion timeout which ignores the cluster size, the probe interval and the amount of suspectors.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimeout(pub Duration);
+
+impl SuspicionTimeout for FixedTimeout {
+	fn timeout(&self, _: NonZeroU32, _: Duration, _: NonZeroU32) -> Duration {
+		self.0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::convert::TryInto;
+
+	use super::*;
+
+	#[test]
+	fn calc_timeout() {
+		let k = NonZeroU32::new(3).unwrap();
+
+		let cases = vec![
+			(1, Duration::from_secs(30)),
+			(2, Duration::from_secs(16)),
+			(3, Duration::from_millis(7810)),
+			(4, Duration::from_secs(2)),
+			(5, Duration::from_secs(2)),
+			(6, Duration::from_secs(2)),
+		];
+
+		for (c, expected) in cases {
+			let result = lifeguard_timeout(
+				k,
+				Duration::from_secs(2),
+				Duration::from_secs(30),
+				c.try_into().unwrap(),
+			);
+			assert_eq!(result, expected);
+		}
+	}
+
+	#[test]
+	fn lifeguard_scales_with_node_count() {
+		let config = SuspicionConfig {
+			alpha: 4.0,
+			beta: 6.0,
+			k: NonZeroU32::new(3).unwrap(),
+		};
+		let one = NonZeroU32::new(1).unwrap();
+		let ping_interval = Duration::from_secs(1);
+
+		let small = config.timeout(one, ping_interval, one);
+		let large = config.timeout(NonZeroU32::new(100).unwrap(), ping_interval, one);
+
+		assert_eq!(small, Duration::from_secs(6));
+		assert_eq!(large, Duration::from_secs(48));
+	}
+}
@@ -0,0 +1,122 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// A [Future] which completes once a [Clock] reaches a deadline.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The source of time for the scheduler.
+///
+/// Every interval and timer of the scheduler reads the current time and sleeps through its [Clock].
+/// [TokioClock] is used by default. [ManualClock] only moves when told to, which allows tests and
+/// simulators to drive each node's time by hand.
+pub trait Clock: Debug + Send + Sync {
+	/// Returns the current time.
+	fn now(&self) -> Instant;
+
+	/// Returns a [Future] which completes once the current time is at or after `deadline`.
+	fn sleep_until(&self, deadline: Instant) -> Sleep;
+
+	/// Returns a [Future] which completes once `d` has passed.
+	fn sleep(&self, d: Duration) -> Sleep {
+		self.sleep_until(self.now() + d)
+	}
+}
+
+/// A [Clock] using the time of the tokio runtime. Follows the paused clock of tokio in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+
+	fn sleep_until(&self, deadline: Instant) -> Sleep {
+		Box::pin(tokio::time::sleep_until(deadline))
+	}
+}
+
+/// A [Clock] which only moves when [ManualClock::advance] or [ManualClock::set] is called.
+#[derive(Debug)]
+pub struct ManualClock {
+	tx: watch::Sender<Instant>,
+	rx: watch::Receiver<Instant>,
+}
+
+impl ManualClock {
+	/// Returns a new [ManualClock] starting at `now`.
+	pub fn new(now: Instant) -> Self {
+		let (tx, rx) = watch::channel(now);
+		Self { tx, rx }
+	}
+
+	/// Moves the clock forward by `d` and wakes every sleeper whose deadline has been reached.
+	pub fn advance(&self, d: Duration) {
+		self.set(self.now() + d);
+	}
+
+	/// Sets the clock to `now` and wakes every sleeper whose deadline has been reached.
+	/// Does nothing if `now` is before the current time, since the clock never moves backwards.
+	pub fn set(&self, now: Instant) {
+		if now > self.now() {
+			// cannot fail, since `self` holds a receiver.
+			let _ = self.tx.send(now);
+		}
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&self) -> Instant {
+		*self.rx.borrow()
+	}
+
+	fn sleep_until(&self, deadline: Instant) -> Sleep {
+		let mut rx = self.rx.clone();
+
+		Box::pin(async move {
+			while *rx.borrow() < deadline {
+				if rx.changed().await.is_err() {
+					// the clock has been dropped and will never reach the deadline.
+					std::future::pending::<()>().await;
+				}
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::future::poll_fn;
+	use std::task::Poll;
+
+	use super::*;
+
+	/// Polls `sleep` once and returns whether it completed.
+	async fn is_ready(sleep: &mut Sleep) -> bool {
+		poll_fn(|cx| Poll::Ready(sleep.as_mut().poll(cx).is_ready())).await
+	}
+
+	#[tokio::test]
+	async fn manual_clock() {
+		let start = Instant::now();
+		let clock = ManualClock::new(start);
+
+		let mut sleep = clock.sleep(Duration::from_secs(10));
+		assert!(!is_ready(&mut sleep).await);
+
+		clock.advance(Duration::from_secs(9));
+		assert!(!is_ready(&mut sleep).await);
+
+		clock.set(start);
+		assert_eq!(clock.now(), start + Duration::from_secs(9));
+		assert!(!is_ready(&mut sleep).await);
+
+		clock.advance(Duration::from_secs(2));
+		assert!(is_ready(&mut sleep).await);
+	}
+}
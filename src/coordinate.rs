@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::CoordinateConfig;
+
+/// Values below this threshold are treated as zero.
+const ZERO_THRESHOLD: f64 = 1.0e-6;
+
+/// The largest absolute value of a component of a valid coordinate in seconds.
+const MAX_VALUE: f64 = 1.0e3;
+
+/// A network coordinate of a node as specified by the *Vivaldi*-paper ([PDF](https://pdos.csail.mit.edu/papers/vivaldi:sigcomm/paper.pdf)).
+///
+/// In addition to the euclidean vector, each coordinate carries a height to model the access link of
+/// a node and an adjustment term which corrects systematic errors of the euclidean model.
+/// All values are given in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinate {
+	/// The euclidean part of the coordinate.
+	pub vec: Box<[f64]>,
+	/// The estimated error of the coordinate.
+	pub error: f64,
+	/// The adjustment term.
+	pub adjustment: f64,
+	/// The height of the coordinate.
+	pub height: f64,
+}
+
+impl Coordinate {
+	/// Creates a new coordinate at the origin.
+	pub fn new(config: &CoordinateConfig) -> Self {
+		Self {
+			vec: vec![0.0; config.dimensionality.get()].into_boxed_slice(),
+			error: config.error_max,
+			adjustment: 0.0,
+			height: config.height_min.as_secs_f64(),
+		}
+	}
+
+	/// Returns `true` if the coordinate has the same dimensionality as `other`.
+	pub fn is_compatible_with(&self, other: &Coordinate) -> bool {
+		self.vec.len() == other.vec.len()
+	}
+
+	/// Returns `true` if all components are finite numbers of at most 1000 seconds.
+	pub fn is_valid(&self) -> bool {
+		let in_range = |v: f64| v.is_finite() && v.abs() <= MAX_VALUE;
+
+		self.vec.iter().copied().all(in_range)
+			&& in_range(self.error)
+			&& in_range(self.adjustment)
+			&& in_range(self.height)
+	}
+
+	/// Returns the estimated round-trip time to `other`, or [None] if the coordinates have different
+	/// dimensionalities or the distance is not a valid [Duration].
+	pub fn distance_to(&self, other: &Coordinate) -> Option<Duration> {
+		if !self.is_compatible_with(other) {
+			return None;
+		}
+
+		Duration::try_from_secs_f64(self.adjusted_distance_to(other)).ok()
+	}
+
+	/// Returns the distance to `other` in seconds including the adjustment terms.
+	/// Both coordinates must have the same dimensionality.
+	fn adjusted_distance_to(&self, other: &Coordinate) -> f64 {
+		let dist = self.raw_distance_to(other);
+		let adjusted = dist + self.adjustment + other.adjustment;
+
+		if adjusted > 0.0 {
+			adjusted
+		} else {
+			dist
+		}
+	}
+
+	/// Returns the distance to `other` in seconds without applying the adjustment terms.
+	/// Both coordinates must have the same dimensionality.
+	fn raw_distance_to(&self, other: &Coordinate) -> f64 {
+		magnitude(self.vec.iter().zip(other.vec.iter()).map(|(a, b)| a - b))
+			+ self.height
+			+ other.height
+	}
+
+	/// Returns a new coordinate which has been moved by `force` seconds away from `other`.
+	/// A negative `force` moves the coordinate towards `other`.
+	fn apply_force<R: Rng>(
+		&self,
+		rng: &mut R,
+		height_min: f64,
+		force: f64,
+		other: &Coordinate,
+	) -> Coordinate {
+		let (unit, mag) = unit_vector_at(rng, &self.vec, &other.vec);
+
+		let vec = self
+			.vec
+			.iter()
+			.zip(unit.iter())
+			.map(|(v, u)| v + u * force)
+			.collect();
+
+		let mut height = self.height;
+		if mag > ZERO_THRESHOLD {
+			height += (self.height + other.height) * force / mag;
+		}
+
+		Coordinate {
+			vec,
+			error: self.error,
+			adjustment: self.adjustment,
+			height: f64::max(height, height_min),
+		}
+	}
+}
+
+fn magnitude<I: Iterator<Item = f64>>(vec: I) -> f64 {
+	vec.map(|v| v * v).sum::<f64>().sqrt()
+}
+
+/// Returns the unit vector pointing from `b` to `a` and the distance between both.
+/// A random unit vector is returned if both are at the same position.
+fn unit_vector_at<R: Rng>(rng: &mut R, a: &[f64], b: &[f64]) -> (Vec<f64>, f64) {
+	let diff: Vec<f64> = a.iter().zip(b.iter()).map(|(a, b)| a - b).collect();
+
+	let mag = magnitude(diff.iter().copied());
+	if mag > ZERO_THRESHOLD {
+		return (diff.into_iter().map(|v| v / mag).collect(), mag);
+	}
+
+	loop {
+		let random: Vec<f64> = (0..a.len()).map(|_| rng.gen::<f64>() - 0.5).collect();
+
+		let mag = magnitude(random.iter().copied());
+		if mag > ZERO_THRESHOLD {
+			return (random.into_iter().map(|v| v / mag).collect(), 0.0);
+		}
+	}
+}
+
+/// Maintains the [Coordinate] of the local node and a cache of the coordinates of other nodes.
+///
+/// The local coordinate is updated with each round-trip time measured to another node.
+#[derive(Debug)]
+pub(crate) struct Vivaldi<R = SmallRng> {
+	config: CoordinateConfig,
+	coord: Coordinate,
+	origin: Coordinate,
+
+	adjustment_index: usize,
+	adjustment_samples: Vec<f64>,
+	latency_samples: HashMap<SocketAddr, Vec<f64>>,
+
+	cache: HashMap<SocketAddr, Coordinate>,
+
+	rng: R,
+}
+
+impl Vivaldi<SmallRng> {
+	pub(crate) fn from_entropy(config: CoordinateConfig) -> Self {
+		Self::new(config, SmallRng::from_entropy())
+	}
+}
+
+impl<R> Vivaldi<R>
+where
+	R: Rng,
+{
+	pub(crate) fn new(config: CoordinateConfig, rng: R) -> Self {
+		Self {
+			coord: Coordinate::new(&config),
+			origin: Coordinate::new(&config),
+			adjustment_index: 0,
+			adjustment_samples: vec![0.0; config.adjustment_window_size],
+			latency_samples: HashMap::new(),
+			cache: HashMap::new(),
+			config,
+			rng,
+		}
+	}
+
+	/// Returns the coordinate of the local node.
+	#[inline]
+	pub(crate) fn coordinate(&self) -> &Coordinate {
+		&self.coord
+	}
+
+	/// Returns the cached coordinate of another node.
+	#[inline]
+	pub(crate) fn get(&self, addr: &SocketAddr) -> Option<&Coordinate> {
+		self.cache.get(addr)
+	}
+
+	/// Removes all state about another node.
+	pub(crate) fn forget(&mut self, addr: &SocketAddr) {
+		self.cache.remove(addr);
+		self.latency_samples.remove(addr);
+	}
+
+	/// Returns the estimated round-trip time to another node, if its coordinate is known.
+	pub(crate) fn distance_to(&self, addr: &SocketAddr) -> Option<Duration> {
+		self.cache.get(addr).and_then(|c| self.coord.distance_to(c))
+	}
+
+	/// Sorts the given addresses by their estimated round-trip time, closest first.
+	/// Addresses without a known coordinate are moved to the end.
+	pub(crate) fn sort_by_distance(&self, addrs: &mut [SocketAddr]) {
+		addrs.sort_by_key(|addr| match self.distance_to(addr) {
+			Some(d) => (false, d),
+			None => (true, Duration::default()),
+		});
+	}
+
+	/// Updates the local coordinate with the coordinate `other` of the node `addr`, which has been received
+	/// in an `ack`, and the measured round-trip time to that node.
+	///
+	/// Incompatible, non-finite or out-of-range coordinates are ignored. The local coordinate is reset
+	/// if it becomes invalid. Returns the updated local coordinate.
+	pub(crate) fn update(
+		&mut self,
+		addr: SocketAddr,
+		other: Coordinate,
+		rtt: Duration,
+	) -> &Coordinate {
+		if !self.coord.is_compatible_with(&other) || !other.is_valid() {
+			return &self.coord;
+		}
+
+		let rtt = self.latency_filter(addr, rtt.as_secs_f64());
+		self.update_vivaldi(&other, rtt);
+		self.update_adjustment(&other, rtt);
+		self.update_gravity();
+
+		if !self.coord.is_valid() {
+			self.coord = Coordinate::new(&self.config);
+		}
+
+		self.cache.insert(addr, other);
+		&self.coord
+	}
+
+	/// Returns the median of the last samples of a node to filter out spikes.
+	fn latency_filter(&mut self, addr: SocketAddr, rtt: f64) -> f64 {
+		let size = self.config.latency_filter_size.get();
+		let samples = self.latency_samples.entry(addr).or_default();
+
+		samples.push(rtt);
+		if samples.len() > size {
+			samples.remove(0);
+		}
+
+		let mut sorted = samples.clone();
+		sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		sorted[sorted.len() / 2]
+	}
+
+	fn update_vivaldi(&mut self, other: &Coordinate, rtt: f64) {
+		let rtt = f64::max(rtt, ZERO_THRESHOLD);
+
+		let dist = self.coord.adjusted_distance_to(other);
+		let total_error = f64::max(self.coord.error + other.error, ZERO_THRESHOLD);
+		let weight = self.coord.error / total_error;
+
+		let ce = self.config.vivaldi_ce;
+		let error = ce * weight * (dist - rtt).abs() / rtt + self.coord.error * (1.0 - ce * weight);
+
+		let force = self.config.vivaldi_cc * weight * (rtt - dist);
+		let height_min = self.config.height_min.as_secs_f64();
+
+		self.coord = self
+			.coord
+			.apply_force(&mut self.rng, height_min, force, other);
+		self.coord.error = f64::min(error, self.config.error_max);
+	}
+
+	fn update_adjustment(&mut self, other: &Coordinate, rtt: f64) {
+		let window = self.adjustment_samples.len();
+		if window == 0 {
+			return;
+		}
+
+		let dist = self.coord.raw_distance_to(other);
+		self.adjustment_samples[self.adjustment_index] = rtt - dist;
+		self.adjustment_index = (self.adjustment_index + 1) % window;
+
+		let sum: f64 = self.adjustment_samples.iter().sum();
+		self.coord.adjustment = sum / (2.0 * window as f64);
+	}
+
+	/// Pulls the coordinate towards the origin to prevent drift of the whole coordinate system.
+	fn update_gravity(&mut self) {
+		let dist = self.origin.adjusted_distance_to(&self.coord);
+		let force = -(dist / self.config.gravity_rho).powi(2);
+		let height_min = self.config.height_min.as_secs_f64();
+
+		self.coord = self
+			.coord
+			.apply_force(&mut self.rng, height_min, force, &self.origin);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::num::NonZeroUsize;
+
+	use rand::rngs::mock::StepRng;
+
+	use super::*;
+
+	fn config() -> CoordinateConfig {
+		CoordinateConfig {
+			dimensionality: NonZeroUsize::new(3).unwrap(),
+			error_max: 1.5,
+			vivaldi_ce: 0.25,
+			vivaldi_cc: 0.25,
+			adjustment_window_size: 0,
+			height_min: Duration::from_micros(10),
+			latency_filter_size: NonZeroUsize::new(1).unwrap(),
+			gravity_rho: 150.0,
+		}
+	}
+
+	fn addr(port: u16) -> SocketAddr {
+		format!("127.0.0.1:{}", port).parse().unwrap()
+	}
+
+	#[test]
+	fn distance_to() {
+		let c = config();
+
+		let mut a = Coordinate::new(&c);
+		a.vec = vec![-0.5, 1.3, 2.4].into_boxed_slice();
+		a.height = 0.0;
+
+		let mut b = Coordinate::new(&c);
+		b.vec = vec![1.2, -2.3, 3.4].into_boxed_slice();
+		b.height = 0.0;
+
+		assert!((a.distance_to(&a).unwrap().as_secs_f64()).abs() < 1e-6);
+		assert!((a.distance_to(&b).unwrap().as_secs_f64() - 4.104875150354758).abs() < 1e-6);
+
+		b.height = 0.7;
+		b.adjustment = 0.1;
+		assert!((a.distance_to(&b).unwrap().as_secs_f64() - 4.904875150354758).abs() < 1e-6);
+
+		// negative adjustments are ignored if they would result in a negative distance
+		b.adjustment = -10.0;
+		assert!((a.distance_to(&b).unwrap().as_secs_f64() - 4.804875150354758).abs() < 1e-6);
+	}
+
+	#[test]
+	fn distance_to_other_dimensionality() {
+		let c = config();
+
+		let a = Coordinate::new(&c);
+		let mut b = Coordinate::new(&c);
+		b.vec = vec![0.0; a.vec.len() + 1].into_boxed_slice();
+
+		assert_eq!(a.distance_to(&b), None);
+	}
+
+	#[test]
+	fn invalid_coordinates_are_ignored() {
+		let rtt = Duration::from_millis(10);
+		let mut v = Vivaldi::new(config(), StepRng::new(0, 1 << 40));
+		let before = v.coordinate().clone();
+
+		let mut huge = Coordinate::new(&config());
+		huge.vec[0] = 1e20;
+		assert!(!huge.is_valid());
+		assert_eq!(before.distance_to(&huge), None);
+		assert_eq!(v.update(addr(1), huge, rtt), &before);
+
+		let mut other = Coordinate::new(&config());
+		other.vec = vec![0.1; 4].into_boxed_slice();
+		assert_eq!(v.update(addr(1), other, rtt), &before);
+
+		assert!(v.get(&addr(1)).is_none());
+		assert_eq!(v.distance_to(&addr(1)), None);
+	}
+
+	#[test]
+	fn update_converges() {
+		let rtt = Duration::from_millis(10);
+
+		let mut a = Vivaldi::new(config(), StepRng::new(0, 1 << 40));
+		let mut b = Vivaldi::new(config(), StepRng::new(1 << 50, 1 << 45));
+
+		for _ in 0..100 {
+			let coord = b.coordinate().clone();
+			a.update(addr(2), coord, rtt);
+
+			let coord = a.coordinate().clone();
+			b.update(addr(1), coord, rtt);
+		}
+
+		let estimate = a.distance_to(&addr(2)).unwrap();
+		let diff = estimate.as_secs_f64() - rtt.as_secs_f64();
+		assert!(diff.abs() < 0.001, "estimate was {:?}", estimate);
+	}
+
+	#[test]
+	fn sort_by_distance() {
+		let mut v = Vivaldi::new(config(), StepRng::new(0, 1 << 40));
+
+		for (port, x) in [(1, 0.3), (2, 0.1), (3, 0.2)].iter() {
+			let mut c = Coordinate::new(&config());
+			c.vec[0] = *x;
+			v.cache.insert(addr(*port), c);
+		}
+
+		let mut addrs = vec![addr(4), addr(1), addr(2), addr(3)];
+		v.sort_by_distance(&mut addrs);
+
+		assert_eq!(addrs, vec![addr(2), addr(3), addr(1), addr(4)]);
+	}
+}
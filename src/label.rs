@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::metrics::Metrics;
+
+/// The first byte of a packet or push-pull header which carries a cluster label.
+const LABEL_TAG: u8 = 0xf4;
+
+/// The max length of a cluster label in bytes.
+pub const MAX_LABEL_LEN: usize = u8::MAX as usize;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LabelError {
+	#[error("cluster label is longer than {} bytes", MAX_LABEL_LEN)]
+	TooLong,
+	#[error("cluster label mismatch: expected `{expected}`, received `{received}`")]
+	Mismatch { expected: String, received: String },
+	#[error("malformed cluster label header")]
+	Malformed,
+}
+
+/// Prefixes outgoing packets and push-pull headers with the cluster label and strips the label
+/// from incoming ones.
+///
+/// A labeled message starts with the following header:
+/// ```text
+/// +-----------+--------+----------------+
+/// | LABEL_TAG | length | label (length) |
+/// +-----------+--------+----------------+
+/// ```
+/// An empty label is never written, so nodes without a label stay compatible with each other.
+/// Incoming messages with a different label, including a missing one, are rejected and counted.
+#[derive(Debug)]
+pub(crate) struct Labeler {
+	label: Box<[u8]>,
+	metrics: Arc<Metrics>,
+}
+
+impl Labeler {
+	pub(crate) fn new(label: &str, metrics: Arc<Metrics>) -> Result<Self, LabelError> {
+		if label.len() > MAX_LABEL_LEN {
+			return Err(LabelError::TooLong);
+		}
+
+		Ok(Self {
+			label: label.as_bytes().into(),
+			metrics,
+		})
+	}
+
+	/// Appends the label header to `out`. Does nothing if the label is empty.
+	pub(crate) fn write_header(&self, out: &mut Vec<u8>) {
+		if self.label.is_empty() {
+			return;
+		}
+
+		out.reserve(self.label.len() + 2);
+		out.push(LABEL_TAG);
+		out.push(self.label.len() as u8);
+		out.extend_from_slice(&self.label);
+	}
+
+	/// Checks the label header of `msg` and returns the remaining message.
+	pub(crate) fn strip_header<'a>(&self, msg: &'a [u8]) -> Result<&'a [u8], LabelError> {
+		let result = Self::split(msg).and_then(|(label, rest)| {
+			if label == &*self.label {
+				Ok(rest)
+			} else {
+				Err(LabelError::Mismatch {
+					expected: String::from_utf8_lossy(&self.label).into_owned(),
+					received: String::from_utf8_lossy(label).into_owned(),
+				})
+			}
+		});
+
+		if result.is_err() {
+			self.metrics.label_rejections.inc();
+		}
+
+		result
+	}
+
+	/// Splits `msg` into its label and the remaining message.
+	fn split(msg: &[u8]) -> Result<(&[u8], &[u8]), LabelError> {
+		match msg {
+			[LABEL_TAG, len, rest @ ..] => {
+				let len = usize::from(*len);
+				if len == 0 || rest.len() < len {
+					return Err(LabelError::Malformed);
+				}
+				Ok(rest.split_at(len))
+			}
+			_ => Ok((&[], msg)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn write_and_strip() {
+		let metrics = Arc::new(Metrics::default());
+		let a = Labeler::new("a", metrics.clone()).unwrap();
+		let b = Labeler::new("bb", metrics.clone()).unwrap();
+		let none = Labeler::new("", metrics.clone()).unwrap();
+
+		let mut msg = Vec::new();
+		a.write_header(&mut msg);
+		msg.extend_from_slice(b"ping");
+
+		assert_eq!(a.strip_header(&msg), Ok(&b"ping"[..]));
+		assert_eq!(
+			b.strip_header(&msg),
+			Err(LabelError::Mismatch {
+				expected: "bb".into(),
+				received: "a".into(),
+			})
+		);
+		assert!(matches!(
+			none.strip_header(&msg),
+			Err(LabelError::Mismatch { .. })
+		));
+
+		let mut msg = Vec::new();
+		none.write_header(&mut msg);
+		msg.extend_from_slice(b"ping");
+
+		assert_eq!(none.strip_header(&msg), Ok(&b"ping"[..]));
+		assert!(matches!(
+			a.strip_header(&msg),
+			Err(LabelError::Mismatch { .. })
+		));
+
+		assert_eq!(
+			a.strip_header(&[LABEL_TAG, 3, b'a']),
+			Err(LabelError::Malformed)
+		);
+		assert_eq!(metrics.label_rejections.get(), 4);
+
+		let long = "x".repeat(MAX_LABEL_LEN + 1);
+		assert_eq!(
+			Labeler::new(&long, metrics).unwrap_err(),
+			LabelError::TooLong
+		);
+	}
+}
@@ -1,13 +1,33 @@
+mod advertise;
 mod awareness;
+mod broadcast;
 mod client;
+mod clock;
 mod consts;
+mod coordinate;
 mod handle;
+mod label;
+mod metrics;
 mod node;
 mod node_set;
 mod ping;
+mod protocol;
+mod rate_limit;
+mod rtt;
 mod scheduler;
+mod seeds;
+mod snapshot;
 mod suspicions;
 
+pub use advertise::AdvertiseError;
+pub use awareness::{Health, HealthChange, HealthChangeReason};
 pub use client::*;
+pub use clock::{Clock, ManualClock, Sleep, TokioClock};
+pub use coordinate::Coordinate;
+pub use label::{LabelError, MAX_LABEL_LEN};
+pub use metrics::*;
 pub use node::{Node, NodeState};
 pub use ping::{PingRequestTarget, PingTarget, RequestSource};
+pub use rate_limit::RateLimitError;
+pub use rtt::Rtt;
+pub use suspicions::{Suspector, SuspicionInfo};
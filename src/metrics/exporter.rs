@@ -0,0 +1,78 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::Metrics;
+
+/// Serves the given [Metrics] in the *Prometheus* text exposition format via HTTP on `addr`.
+///
+/// Every request is answered with the current metrics, regardless of its path or method.
+/// The future only returns if binding the listener fails.
+pub async fn serve(metrics: Arc<Metrics>, addr: SocketAddr) -> io::Result<()> {
+	let listener = TcpListener::bind(addr).await?;
+
+	loop {
+		let (stream, _) = match listener.accept().await {
+			Ok(s) => s,
+			Err(_) => continue,
+		};
+
+		let metrics = metrics.clone();
+		tokio::spawn(async move {
+			let _ = respond(stream, &metrics).await;
+		});
+	}
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+	// The request itself is irrelevant, but it has to be read before responding.
+	let mut buf = [0u8; 1024];
+	let _ = stream.read(&mut buf).await?;
+
+	let mut body = String::new();
+	metrics.render(&mut body).map_err(io::Error::other)?;
+
+	let head = format!(
+		"HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		body.len()
+	);
+
+	stream.write_all(head.as_bytes()).await?;
+	stream.write_all(body.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn responds_with_metrics() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		let metrics = Metrics::default();
+		metrics.acks.add(2);
+
+		let server = tokio::spawn(async move {
+			let (stream, _) = listener.accept().await.unwrap();
+			respond(stream, &metrics).await.unwrap();
+		});
+
+		let mut client = TcpStream::connect(addr).await.unwrap();
+		client
+			.write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+			.await
+			.unwrap();
+
+		let mut response = String::new();
+		client.read_to_string(&mut response).await.unwrap();
+		server.await.unwrap();
+
+		assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+		assert!(response.contains("\nswimmers_acks_total 2\n"));
+	}
+}
@@ -0,0 +1,382 @@
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "prometheus")]
+mod exporter;
+
+#[cfg(feature = "prometheus")]
+pub use exporter::serve;
+
+/// The kind of a message sent or received by the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageType {
+	Ping,
+	PingRequest,
+	Ack,
+	Nack,
+	Alive,
+	Suspect,
+	Dead,
+	PushPull,
+}
+
+impl MessageType {
+	pub(crate) const ALL: [MessageType; 8] = [
+		MessageType::Ping,
+		MessageType::PingRequest,
+		MessageType::Ack,
+		MessageType::Nack,
+		MessageType::Alive,
+		MessageType::Suspect,
+		MessageType::Dead,
+		MessageType::PushPull,
+	];
+
+	fn label(self) -> &'static str {
+		match self {
+			MessageType::Ping => "ping",
+			MessageType::PingRequest => "ping_request",
+			MessageType::Ack => "ack",
+			MessageType::Nack => "nack",
+			MessageType::Alive => "alive",
+			MessageType::Suspect => "suspect",
+			MessageType::Dead => "dead",
+			MessageType::PushPull => "push_pull",
+		}
+	}
+}
+
+/// A monotonically increasing counter.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+	#[inline]
+	pub(crate) fn inc(&self) {
+		self.add(1);
+	}
+
+	#[inline]
+	pub(crate) fn add(&self, n: u64) {
+		self.0.fetch_add(n, Ordering::Relaxed);
+	}
+
+	/// Returns the current value of the counter.
+	#[inline]
+	pub fn get(&self) -> u64 {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+/// A value which can go up and down.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+	#[inline]
+	pub(crate) fn set(&self, n: u64) {
+		self.0.store(n, Ordering::Relaxed);
+	}
+
+	/// Returns the current value of the gauge.
+	#[inline]
+	pub fn get(&self) -> u64 {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+/// A histogram of durations with fixed buckets.
+#[derive(Debug)]
+pub struct Histogram {
+	/// Upper bounds of the buckets in milliseconds.
+	bounds: &'static [u64],
+	/// Non-cumulative bucket counts. The last bucket is `+Inf`.
+	buckets: Box<[AtomicU64]>,
+	sum_micros: AtomicU64,
+	count: AtomicU64,
+}
+
+impl Histogram {
+	const DEFAULT_BOUNDS: &'static [u64] = &[5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+	fn new(bounds: &'static [u64]) -> Self {
+		let buckets = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+
+		Self {
+			bounds,
+			buckets,
+			sum_micros: AtomicU64::new(0),
+			count: AtomicU64::new(0),
+		}
+	}
+
+	pub(crate) fn observe(&self, d: Duration) {
+		let i = self
+			.bounds
+			.iter()
+			.position(|&b| d <= Duration::from_millis(b))
+			.unwrap_or(self.bounds.len());
+
+		self.buckets[i].fetch_add(1, Ordering::Relaxed);
+		self.sum_micros
+			.fetch_add(d.as_micros() as u64, Ordering::Relaxed);
+		self.count.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Returns the amount of observed values.
+	#[inline]
+	pub fn count(&self) -> u64 {
+		self.count.load(Ordering::Relaxed)
+	}
+
+	/// Returns the sum of all observed values.
+	#[inline]
+	pub fn sum(&self) -> Duration {
+		Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+	}
+}
+
+impl Default for Histogram {
+	fn default() -> Self {
+		Self::new(Self::DEFAULT_BOUNDS)
+	}
+}
+
+/// A [Counter] for each [MessageType].
+#[derive(Debug, Default)]
+pub struct MessageCounters([Counter; 8]);
+
+impl MessageCounters {
+	#[inline]
+	pub(crate) fn inc(&self, kind: MessageType) {
+		self.get(kind).inc();
+	}
+
+	#[inline]
+	pub(crate) fn add(&self, kind: MessageType, n: usize) {
+		self.get(kind).add(n as u64);
+	}
+
+	/// Returns the [Counter] for the given [MessageType].
+	#[inline]
+	pub fn get(&self, kind: MessageType) -> &Counter {
+		&self.0[kind as usize]
+	}
+}
+
+/// Metrics collected by a node. The metrics are shared between the components of a node and can be read at any time.
+///
+/// Use [Metrics::render] to get the metrics in the *Prometheus* text exposition format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+	/// Direct probes sent.
+	pub probes: Counter,
+	/// Indirect probes started after a failed direct probe.
+	pub indirect_probes: Counter,
+	/// `acks` received for direct or indirect probes.
+	pub acks: Counter,
+	/// `nacks` received for indirect probes.
+	pub nacks: Counter,
+	/// Suspicions started.
+	pub suspicions_started: Counter,
+	/// Confirmations of ongoing suspicions by other nodes.
+	pub suspicions_confirmed: Counter,
+	/// Suspicions about this node which have been refuted.
+	pub refutations: Counter,
+	/// Packets and push-pull headers rejected because of a different cluster label.
+	pub label_rejections: Counter,
+	/// Durations of push-pull syncs.
+	pub push_pull_duration: Histogram,
+	/// Round-trip times of direct probes.
+	pub probe_rtt: Histogram,
+	/// Round-trip times of indirect probes, which include the nodes probing the target.
+	pub indirect_probe_rtt: Histogram,
+	/// Bytes received per [MessageType].
+	pub bytes_in: MessageCounters,
+	/// Bytes sent per [MessageType].
+	pub bytes_out: MessageCounters,
+	/// Incoming messages dropped by the rate limiter per [MessageType].
+	pub dropped: MessageCounters,
+	/// Amount of messages waiting in the broadcast queue.
+	pub broadcast_queue_depth: Gauge,
+}
+
+impl Metrics {
+	/// Writes all metrics in the *Prometheus* text exposition format to `w`.
+	pub fn render<W: Write>(&self, w: &mut W) -> fmt::Result {
+		let counters = [
+			("probes", "Direct probes sent.", &self.probes),
+			(
+				"indirect_probes",
+				"Indirect probes started.",
+				&self.indirect_probes,
+			),
+			("acks", "Acks received.", &self.acks),
+			("nacks", "Nacks received.", &self.nacks),
+			(
+				"suspicions_started",
+				"Suspicions started.",
+				&self.suspicions_started,
+			),
+			(
+				"suspicions_confirmed",
+				"Suspicion confirmations received.",
+				&self.suspicions_confirmed,
+			),
+			(
+				"refutations",
+				"Refuted suspicions about this node.",
+				&self.refutations,
+			),
+			(
+				"label_rejections",
+				"Messages rejected because of a different cluster label.",
+				&self.label_rejections,
+			),
+		];
+
+		for (name, help, counter) in counters.iter() {
+			writeln!(w, "# HELP swimmers_{}_total {}", name, help)?;
+			writeln!(w, "# TYPE swimmers_{}_total counter", name)?;
+			writeln!(w, "swimmers_{}_total {}", name, counter.get())?;
+		}
+
+		for (name, help, counters) in [
+			(
+				"bytes_in",
+				"Bytes received per message type.",
+				&self.bytes_in,
+			),
+			("bytes_out", "Bytes sent per message type.", &self.bytes_out),
+			(
+				"dropped",
+				"Messages dropped by the rate limiter per message type.",
+				&self.dropped,
+			),
+		]
+		.iter()
+		{
+			writeln!(w, "# HELP swimmers_{}_total {}", name, help)?;
+			writeln!(w, "# TYPE swimmers_{}_total counter", name)?;
+			for kind in MessageType::ALL.iter() {
+				writeln!(
+					w,
+					"swimmers_{}_total{{type=\"{}\"}} {}",
+					name,
+					kind.label(),
+					counters.get(*kind).get()
+				)?;
+			}
+		}
+
+		for (name, help, h) in [
+			(
+				"push_pull_duration",
+				"Durations of push-pull syncs.",
+				&self.push_pull_duration,
+			),
+			(
+				"probe_rtt",
+				"Round-trip times of direct probes.",
+				&self.probe_rtt,
+			),
+			(
+				"indirect_probe_rtt",
+				"Round-trip times of indirect probes.",
+				&self.indirect_probe_rtt,
+			),
+		]
+		.iter()
+		{
+			writeln!(w, "# HELP swimmers_{}_seconds {}", name, help)?;
+			writeln!(w, "# TYPE swimmers_{}_seconds histogram", name)?;
+			let mut cumulative = 0;
+			for (i, bucket) in h.buckets.iter().enumerate() {
+				cumulative += bucket.load(Ordering::Relaxed);
+				match h.bounds.get(i) {
+					Some(&b) => writeln!(
+						w,
+						"swimmers_{}_seconds_bucket{{le=\"{}\"}} {}",
+						name,
+						b as f64 / 1000.0,
+						cumulative
+					)?,
+					None => writeln!(
+						w,
+						"swimmers_{}_seconds_bucket{{le=\"+Inf\"}} {}",
+						name, cumulative
+					)?,
+				}
+			}
+			writeln!(w, "swimmers_{}_seconds_sum {}", name, h.sum().as_secs_f64())?;
+			writeln!(w, "swimmers_{}_seconds_count {}", name, h.count())?;
+		}
+
+		writeln!(
+			w,
+			"# HELP swimmers_broadcast_queue_depth Messages waiting in the broadcast queue."
+		)?;
+		writeln!(w, "# TYPE swimmers_broadcast_queue_depth gauge")?;
+		writeln!(
+			w,
+			"swimmers_broadcast_queue_depth {}",
+			self.broadcast_queue_depth.get()
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn render() {
+		let m = Metrics::default();
+
+		m.probes.add(3);
+		m.bytes_out.add(MessageType::Ack, 42);
+		m.dropped.inc(MessageType::Ack);
+		m.push_pull_duration.observe(Duration::from_millis(7));
+		m.push_pull_duration.observe(Duration::from_secs(20));
+		m.probe_rtt.observe(Duration::from_millis(3));
+		m.broadcast_queue_depth.set(5);
+
+		let mut s = String::new();
+		m.render(&mut s).unwrap();
+
+		let lines: Vec<_> = s.lines().collect();
+		assert!(lines.contains(&"swimmers_probes_total 3"));
+		assert!(lines.contains(&"swimmers_bytes_out_total{type=\"ack\"} 42"));
+		assert!(lines.contains(&"swimmers_bytes_in_total{type=\"ack\"} 0"));
+		assert!(lines.contains(&"swimmers_dropped_total{type=\"ack\"} 1"));
+		assert!(lines.contains(&"swimmers_dropped_total{type=\"ping\"} 0"));
+		assert!(lines.contains(&"swimmers_push_pull_duration_seconds_bucket{le=\"0.005\"} 0"));
+		assert!(lines.contains(&"swimmers_push_pull_duration_seconds_bucket{le=\"0.01\"} 1"));
+		assert!(lines.contains(&"swimmers_push_pull_duration_seconds_bucket{le=\"10\"} 1"));
+		assert!(lines.contains(&"swimmers_push_pull_duration_seconds_bucket{le=\"+Inf\"} 2"));
+		assert!(lines.contains(&"swimmers_push_pull_duration_seconds_count 2"));
+		assert!(lines.contains(&"swimmers_probe_rtt_seconds_bucket{le=\"0.005\"} 1"));
+		assert!(lines.contains(&"swimmers_probe_rtt_seconds_count 1"));
+		assert!(lines.contains(&"swimmers_indirect_probe_rtt_seconds_count 0"));
+		assert!(lines.contains(&"swimmers_broadcast_queue_depth 5"));
+	}
+
+	#[test]
+	fn histogram_buckets_are_upper_inclusive() {
+		let h = Histogram::default();
+
+		h.observe(Duration::from_millis(5));
+		h.observe(Duration::from_micros(5001));
+		h.observe(Duration::from_secs(10) + Duration::from_nanos(1));
+
+		let buckets: Vec<_> = h
+			.buckets
+			.iter()
+			.map(|b| b.load(Ordering::Relaxed))
+			.collect();
+		assert_eq!(buckets[0], 1);
+		assert_eq!(buckets[1], 1);
+		assert_eq!(buckets[h.bounds.len()], 1);
+	}
+}
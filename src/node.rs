@@ -3,6 +3,8 @@ use std::net::SocketAddr;
 
 use thiserror::Error;
 
+use crate::rtt::Rtt;
+
 use NodeState::{Alive, Dead, Left, Suspect};
 
 #[derive(Debug, Error)]
@@ -164,6 +166,8 @@ pub struct Node {
 	pub state: NodeState,
 	/// Optional metadata of a node.
 	pub metadata: Option<Box<[u8]>>,
+	/// The round-trip time estimate of the node. This value is measured locally and never gossiped.
+	pub rtt: Option<Rtt>,
 }
 
 #[cfg(test)]
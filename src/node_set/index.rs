@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use crate::node::NodeState;
+
+/// The state of a [Node](crate::Node) without its incarnation number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StateKind {
+	Alive,
+	Suspect,
+	Dead,
+	Left,
+}
+
+impl From<&NodeState> for StateKind {
+	fn from(state: &NodeState) -> Self {
+		match state {
+			NodeState::Alive(_) => StateKind::Alive,
+			NodeState::Suspect(_) => StateKind::Suspect,
+			NodeState::Dead(_) => StateKind::Dead,
+			NodeState::Left => StateKind::Left,
+		}
+	}
+}
+
+/// The addresses of all nodes grouped by their [StateKind].
+#[derive(Debug, Default)]
+pub(super) struct StateIndex {
+	sets: [HashSet<SocketAddr>; 4],
+}
+
+impl StateIndex {
+	#[inline]
+	pub(super) fn get(&self, kind: StateKind) -> &HashSet<SocketAddr> {
+		&self.sets[kind as usize]
+	}
+
+	/// Moves `addr` from the set of the `previous` state to the set of the `current` state.
+	/// [None] means that the node does not exist.
+	pub(super) fn update(
+		&mut self,
+		addr: &SocketAddr,
+		previous: Option<StateKind>,
+		current: Option<StateKind>,
+	) {
+		if previous == current {
+			return;
+		}
+
+		if let Some(kind) = previous {
+			self.sets[kind as usize].remove(addr);
+		}
+
+		if let Some(kind) = current {
+			self.sets[kind as usize].insert(*addr);
+		}
+	}
+}
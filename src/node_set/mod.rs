@@ -0,0 +1,855 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use index::StateIndex;
+use order::ProbeOrder;
+use rand::rngs::SmallRng;
+use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+use tokio::time::Instant;
+
+use crate::clock::{Clock, TokioClock};
+use crate::node::{Node, NodeState};
+use crate::rtt::Rtt;
+
+mod index;
+mod order;
+
+pub(crate) use index::StateKind;
+
+pub(crate) enum InsertionResult<'a> {
+	Unchanged,
+	Equal(&'a Node),
+	Updated(&'a Node),
+	Inserted(&'a Node),
+}
+
+/// Describes whether a node is connected to the rest of the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Isolation {
+	/// At least one peer is [NodeState::Alive] or [NodeState::Suspect].
+	Connected,
+	/// Every known peer is [NodeState::Dead] or [NodeState::Left].
+	PeersDown,
+	/// There are no known peers.
+	Alone,
+}
+
+/// An [Iterator] returning the [SocketAddr] for each [Node] **exactly once** in probe order.
+#[derive(Debug)]
+pub(crate) struct Iter<'a, R> {
+	src: &'a mut NodeSet<R>,
+	visited: HashSet<SocketAddr>,
+	active_nodes: usize,
+}
+
+impl<'a, R> Iterator for Iter<'a, R>
+where
+	R: Rng,
+{
+	type Item = SocketAddr;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while self.visited.len() < self.active_nodes {
+			let addr = self.src.next_probe_target()?;
+
+			if self.visited.insert(addr) {
+				return Some(addr);
+			}
+		}
+
+		None
+	}
+}
+
+/// A mutable reference to a [Node] of a [NodeSet]. The [Node] can only be changed through the methods
+/// of [NodeMut], which keep the bookkeeping of the [NodeSet] up to date.
+#[derive(Debug)]
+pub(crate) struct NodeMut<'a, R: Rng> {
+	node: &'a mut Node,
+	previous: StateKind,
+	states: &'a mut StateIndex,
+	order: &'a mut ProbeOrder,
+	dead_since: &'a mut HashMap<SocketAddr, Instant>,
+	rng: &'a mut R,
+	now: Instant,
+}
+
+impl<R: Rng> Deref for NodeMut<'_, R> {
+	type Target = Node;
+
+	fn deref(&self) -> &Self::Target {
+		self.node
+	}
+}
+
+impl<R: Rng> NodeMut<'_, R> {
+	/// Changes the state of the [Node] using `f`, e.g. [NodeState::kill], and returns its result.
+	pub(crate) fn update_state<F, T>(&mut self, f: F) -> T
+	where
+		F: FnOnce(&mut NodeState) -> T,
+	{
+		let result = f(&mut self.node.state);
+		self.sync();
+		result
+	}
+
+	/// Replaces the state of the [Node].
+	pub(crate) fn set_state(&mut self, state: NodeState) {
+		self.update_state(|s| *s = state);
+	}
+
+	/// Sets the [Node] to [NodeState::Alive] with `incarnation` or the current incarnation number plus 1,
+	/// whichever is higher. Does nothing if the [Node] left.
+	pub(crate) fn set_incarnation(&mut self, incarnation: u64) {
+		self.update_state(|s| s.reincarnate_at(incarnation));
+	}
+
+	/// Replaces the metadata of the [Node].
+	pub(crate) fn set_metadata(&mut self, metadata: Option<Box<[u8]>>) {
+		self.node.metadata = metadata;
+	}
+
+	fn sync(&mut self) {
+		track_death(self.dead_since, self.node, self.now);
+		let current = StateKind::from(&self.node.state);
+		self.states
+			.update(&self.node.addr, Some(self.previous), Some(current));
+		track_probe_order(self.order, self.rng, self.previous, self.node);
+		self.previous = current;
+	}
+}
+
+/// Records the time a [Node] died. Forgets the time once the [Node] is no longer [NodeState::Dead].
+fn track_death(dead_since: &mut HashMap<SocketAddr, Instant>, node: &Node, now: Instant) {
+	if let NodeState::Dead(_) = node.state {
+		dead_since.entry(node.addr).or_insert(now);
+	} else {
+		dead_since.remove(&node.addr);
+	}
+}
+
+/// Returns up to `k` distinct random nodes of `map` for which `filter` returns `true`, excluding the
+/// nodes in `exclude`.
+fn sample<R, F>(
+	map: &HashMap<SocketAddr, Node>,
+	rng: &mut R,
+	k: usize,
+	exclude: &[SocketAddr],
+	mut filter: F,
+) -> Vec<SocketAddr>
+where
+	R: Rng,
+	F: FnMut(&Node) -> bool,
+{
+	map.values()
+		.filter(|n| !exclude.contains(&n.addr) && filter(n))
+		.map(|n| n.addr)
+		.choose_multiple(rng, k)
+}
+
+/// See [NodeSet::is_gossip_target].
+fn is_gossip_target(
+	node: &Node,
+	dead_since: &HashMap<SocketAddr, Instant>,
+	gossip_to_dead: Duration,
+	now: Instant,
+) -> bool {
+	match node.state {
+		NodeState::Alive(_) | NodeState::Suspect(_) => true,
+		NodeState::Dead(_) => match dead_since.get(&node.addr) {
+			Some(&since) => now.saturating_duration_since(since) <= gossip_to_dead,
+			None => false,
+		},
+		NodeState::Left => false,
+	}
+}
+
+/// Adds a [Node] to the [ProbeOrder] once it no longer is [NodeState::Left] and removes it once it left.
+/// New nodes are treated as if they had left.
+fn track_probe_order<R: Rng>(
+	order: &mut ProbeOrder,
+	rng: &mut R,
+	previous: StateKind,
+	node: &Node,
+) {
+	match (previous == StateKind::Left, node.state == NodeState::Left) {
+		(false, true) => order.remove(&node.addr),
+		(true, false) => order.insert(node.addr, rng),
+		_ => {}
+	}
+}
+
+#[derive(Debug)]
+pub(crate) struct NodeSet<R> {
+	map: HashMap<SocketAddr, Node>,
+	/// The probe order of all nodes which did not leave the cluster.
+	order: ProbeOrder,
+	/// The addresses of all nodes grouped by state.
+	states: StateIndex,
+	/// The time each [NodeState::Dead] node died.
+	dead_since: HashMap<SocketAddr, Instant>,
+
+	rng: R,
+	clock: Arc<dyn Clock>,
+}
+
+impl<R> NodeSet<R>
+where
+	R: Rng,
+{
+	/// Returns an [Iterator] returning the [SocketAddr] for each [Node] **exactly once** in probe order.
+	/// Nodes which left the cluster are skipped.
+	pub(crate) fn iter_unique_random_addrs<'a>(&'a mut self) -> Option<Iter<'a, R>> {
+		if self.order.len() == 0 {
+			return None;
+		}
+
+		let active_nodes = self.order.len();
+
+		Some(Iter {
+			visited: HashSet::with_capacity(active_nodes),
+			src: self,
+			active_nodes,
+		})
+	}
+
+	/// Returns the next node to probe in the round-robin order of the *SWIM*-paper. Nodes which left the
+	/// cluster are skipped. Returns [None] if there are no nodes to probe.
+	pub(crate) fn next_probe_target(&mut self) -> Option<SocketAddr> {
+		self.order.next(&mut self.rng)
+	}
+
+	pub(crate) fn insert(&mut self, node: Node) -> InsertionResult {
+		match self.map.entry(node.addr) {
+			Entry::Vacant(entry) => {
+				let node = entry.insert(node);
+				track_death(&mut self.dead_since, node, self.clock.now());
+				let kind = StateKind::from(&node.state);
+				self.states.update(&node.addr, None, Some(kind));
+				track_probe_order(&mut self.order, &mut self.rng, StateKind::Left, node);
+				InsertionResult::Inserted(node)
+			}
+			Entry::Occupied(entry) => {
+				let current = entry.into_mut();
+				match Ord::cmp(&node.state, &current.state) {
+					Ordering::Less => InsertionResult::Unchanged,
+					Ordering::Equal => InsertionResult::Equal(current),
+					Ordering::Greater => {
+						let previous = StateKind::from(&current.state);
+						let rtt = current.rtt;
+						*current = node;
+						current.rtt = rtt;
+						track_death(&mut self.dead_since, current, self.clock.now());
+						let kind = StateKind::from(&current.state);
+						self.states
+							.update(&current.addr, Some(previous), Some(kind));
+						track_probe_order(&mut self.order, &mut self.rng, previous, current);
+						InsertionResult::Updated(current)
+					}
+				}
+			}
+		}
+	}
+
+	#[inline]
+	pub(crate) fn get_mut(&mut self, addr: &SocketAddr) -> Option<NodeMut<'_, R>> {
+		let node = self.map.get_mut(addr)?;
+
+		Some(NodeMut {
+			previous: StateKind::from(&node.state),
+			states: &mut self.states,
+			node,
+			order: &mut self.order,
+			dead_since: &mut self.dead_since,
+			rng: &mut self.rng,
+			now: self.clock.now(),
+		})
+	}
+
+	/// Returns up to `k` distinct random nodes for which `filter` returns `true`, excluding the nodes in
+	/// `exclude`. Every matching node is equally likely to be chosen.
+	///
+	/// Unlike [NodeSet::iter_unique_random_addrs], this does not affect the probe order.
+	pub(crate) fn sample<F>(
+		&mut self,
+		k: usize,
+		exclude: &[SocketAddr],
+		filter: F,
+	) -> Vec<SocketAddr>
+	where
+		F: FnMut(&Node) -> bool,
+	{
+		sample(&self.map, &mut self.rng, k, exclude, filter)
+	}
+
+	/// Returns up to `k` random gossip targets. See [NodeSet::is_gossip_target] for the rules.
+	pub(crate) fn gossip_targets(&mut self, k: usize, gossip_to_dead: Duration) -> Vec<SocketAddr> {
+		let now = self.clock.now();
+		let dead_since = &self.dead_since;
+
+		sample(&self.map, &mut self.rng, k, &[], |node| {
+			is_gossip_target(node, dead_since, gossip_to_dead, now)
+		})
+	}
+
+	/// Returns the [SocketAddr] of a random [NodeState::Dead] node.
+	///
+	/// Dead nodes are kept until they get reclaimed, so the returned node died recently. Contacting it
+	/// helps to heal partitions.
+	pub(crate) fn random_dead_addr(&mut self) -> Option<SocketAddr> {
+		self.states
+			.get(StateKind::Dead)
+			.iter()
+			.copied()
+			.choose(&mut self.rng)
+	}
+}
+
+impl Default for NodeSet<SmallRng> {
+	fn default() -> Self {
+		Self::new(SmallRng::from_entropy())
+	}
+}
+
+impl<R> NodeSet<R> {
+	pub(crate) fn new(rng: R) -> Self {
+		Self {
+			map: HashMap::new(),
+			order: ProbeOrder::default(),
+			states: StateIndex::default(),
+			dead_since: HashMap::new(),
+			rng,
+			clock: Arc::new(TokioClock),
+		}
+	}
+
+	/// Reads the time of death of nodes from `clock` instead of the [TokioClock].
+	pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// Returns the total amount of nodes.
+	///
+	/// Use `counts` if you need the amount of nodes grouped by state.
+	#[inline]
+	pub(crate) fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	/// Returns `true` if the [NodeMap] contains the given [SocketAddr].
+	#[inline]
+	pub(crate) fn contains(&self, addr: &SocketAddr) -> bool {
+		self.map.contains_key(addr)
+	}
+
+	/// Updates the [Rtt] estimate of a [Node] with a new sample.
+	/// Returns [None] if the [Node] does not exist.
+	pub(crate) fn update_rtt(&mut self, addr: &SocketAddr, sample: Duration) -> Option<&Rtt> {
+		let node = self.map.get_mut(addr)?;
+
+		match node.rtt.as_mut() {
+			Some(rtt) => rtt.update(sample),
+			None => node.rtt = Some(Rtt::new(sample)),
+		}
+
+		node.rtt.as_ref()
+	}
+
+	#[inline]
+	pub(crate) fn get(&self, addr: &SocketAddr) -> Option<&Node> {
+		self.map.get(addr)
+	}
+
+	#[inline]
+	pub(crate) fn remove(&mut self, addr: &SocketAddr) -> Option<Node> {
+		let node = self.map.remove(addr)?;
+
+		self.dead_since.remove(addr);
+		self.order.remove(addr);
+		self.states
+			.update(addr, Some(StateKind::from(&node.state)), None);
+
+		Some(node)
+	}
+
+	/// Returns `true` if the [Node] should receive gossip.
+	///
+	/// [NodeState::Alive] and [NodeState::Suspect] nodes always receive gossip. [NodeState::Dead] nodes
+	/// receive gossip for `gossip_to_dead` after their death, so a node which was declared dead by mistake
+	/// learns about it quickly and can refute. [NodeState::Left] nodes never receive gossip.
+	pub(crate) fn is_gossip_target(&self, addr: &SocketAddr, gossip_to_dead: Duration) -> bool {
+		match self.map.get(addr) {
+			Some(node) => {
+				is_gossip_target(node, &self.dead_since, gossip_to_dead, self.clock.now())
+			}
+			None => false,
+		}
+	}
+
+	#[inline]
+	pub(crate) fn get_map(&self) -> &HashMap<SocketAddr, Node> {
+		self.map.borrow()
+	}
+
+	/// Returns the [Isolation] of the local node with the given [SocketAddr].
+	pub(crate) fn isolation(&self, local: &SocketAddr) -> Isolation {
+		let peers = self.len() - usize::from(self.contains(local));
+
+		let connected = [StateKind::Alive, StateKind::Suspect]
+			.iter()
+			.map(|kind| self.states.get(*kind))
+			.any(|set| set.len() > usize::from(set.contains(local)));
+
+		if peers == 0 {
+			Isolation::Alone
+		} else if connected {
+			Isolation::Connected
+		} else {
+			Isolation::PeersDown
+		}
+	}
+
+	/// Returns the amount of nodes with each state. The amounts are ordered as follows:
+	/// 1. [NodeState::Alive]
+	/// 2. [NodeState::Suspect]
+	/// 3. [NodeState::Dead]
+	/// 4. [NodeState::Left]
+	pub(crate) fn counts(&self) -> (usize, usize, usize, usize) {
+		(
+			self.count(StateKind::Alive),
+			self.count(StateKind::Suspect),
+			self.count(StateKind::Dead),
+			self.count(StateKind::Left),
+		)
+	}
+
+	/// Returns the amount of nodes with the given [StateKind].
+	#[inline]
+	pub(crate) fn count(&self, kind: StateKind) -> usize {
+		self.states.get(kind).len()
+	}
+
+	/// Returns an [Iterator] over all nodes with the given [StateKind].
+	pub(crate) fn nodes_with_state(&self, kind: StateKind) -> impl Iterator<Item = &Node> + '_ {
+		self.states
+			.get(kind)
+			.iter()
+			.filter_map(move |addr| self.map.get(addr))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::{Ipv4Addr, SocketAddrV4};
+
+	use super::*;
+
+	use crate::clock::ManualClock;
+	use crate::node::{Node, NodeState};
+	use rand::rngs::mock::StepRng;
+
+	fn make_addr(port: u16) -> SocketAddr {
+		SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port))
+	}
+
+	#[test]
+	fn next_probe_target_returns_none_if_map_is_empty() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+		assert_eq!(n.next_probe_target(), None);
+	}
+
+	#[test]
+	fn next_probe_target_starts_new_rounds() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		let addr = make_addr(1);
+		n.insert(Node {
+			addr,
+			state: NodeState::Alive(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		assert_eq!(n.next_probe_target(), Some(addr));
+		assert_eq!(n.next_probe_target(), Some(addr));
+	}
+
+	#[test]
+	fn probe_order_skips_left_nodes() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..10 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: if i % 2 == 0 {
+					NodeState::Alive(i.into())
+				} else {
+					NodeState::Left
+				},
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		assert_eq!(n.order.len(), 5);
+		assert!(!n.order.contains(&make_addr(1)));
+
+		n.get_mut(&make_addr(0)).unwrap().set_state(NodeState::Left);
+		assert_eq!(n.order.len(), 4);
+
+		n.get_mut(&make_addr(1))
+			.unwrap()
+			.set_state(NodeState::Alive(2));
+		assert!(n.order.contains(&make_addr(1)));
+
+		n.remove(&make_addr(1));
+		assert_eq!(n.order.len(), 4);
+	}
+
+	#[test]
+	fn iter_unique_random_addrs_returns_none_if_there_are_no_nodes() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		assert!(n.iter_unique_random_addrs().is_none());
+
+		let addr = make_addr(1);
+		n.insert(Node {
+			addr,
+			state: NodeState::Alive(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		n.remove(&addr);
+
+		assert!(n.iter_unique_random_addrs().is_none());
+	}
+
+	#[test]
+	fn iter_unique_random_addrs_only_returns_unique_adds() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..10 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: NodeState::Alive(i.into()),
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let iter = n.iter_unique_random_addrs().unwrap();
+		let mut set = HashSet::with_capacity(10);
+
+		for a in iter {
+			set.insert(a);
+		}
+
+		assert_eq!(set.len(), 10);
+	}
+
+	#[test]
+	fn update_rtt_survives_state_updates() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		assert!(n
+			.update_rtt(&make_addr(1), Duration::from_millis(10))
+			.is_none());
+
+		n.insert(Node {
+			addr: make_addr(1),
+			state: NodeState::Alive(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		let rtt = *n
+			.update_rtt(&make_addr(1), Duration::from_millis(10))
+			.unwrap();
+		assert_eq!(rtt.srtt, Duration::from_millis(10));
+
+		n.insert(Node {
+			addr: make_addr(1),
+			state: NodeState::Suspect(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		assert_eq!(n.get(&make_addr(1)).unwrap().rtt, Some(rtt));
+	}
+
+	#[test]
+	fn isolation() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		let local = make_addr(0);
+		n.insert(Node {
+			addr: local,
+			state: NodeState::Alive(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		assert_eq!(n.isolation(&local), Isolation::Alone);
+		assert_eq!(n.random_dead_addr(), None);
+
+		for (i, state) in vec![NodeState::Dead(1), NodeState::Left]
+			.into_iter()
+			.enumerate()
+		{
+			n.insert(Node {
+				addr: make_addr(i as u16 + 1),
+				state,
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		assert_eq!(n.isolation(&local), Isolation::PeersDown);
+		assert_eq!(n.random_dead_addr(), Some(make_addr(1)));
+
+		n.insert(Node {
+			addr: make_addr(3),
+			state: NodeState::Suspect(1),
+			metadata: None,
+			rtt: None,
+		});
+
+		assert_eq!(n.isolation(&local), Isolation::Connected);
+	}
+
+	#[test]
+	fn counts_follow_state_changes() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..6 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: NodeState::Alive(1),
+				metadata: None,
+				rtt: None,
+			});
+		}
+		assert_eq!(n.counts(), (6, 0, 0, 0));
+
+		n.insert(Node {
+			addr: make_addr(0),
+			state: NodeState::Suspect(1),
+			metadata: None,
+			rtt: None,
+		});
+		n.get_mut(&make_addr(1))
+			.unwrap()
+			.update_state(NodeState::suspect)
+			.unwrap();
+		n.get_mut(&make_addr(2))
+			.unwrap()
+			.update_state(NodeState::kill)
+			.unwrap();
+		n.get_mut(&make_addr(3))
+			.unwrap()
+			.update_state(NodeState::leave)
+			.unwrap();
+		n.remove(&make_addr(4));
+		n.remove(&make_addr(4));
+
+		assert_eq!(n.counts(), (1, 2, 1, 1));
+		assert_eq!(n.count(StateKind::Suspect), 2);
+
+		let mut suspects: Vec<_> = n
+			.nodes_with_state(StateKind::Suspect)
+			.map(|n| n.addr)
+			.collect();
+		suspects.sort();
+		assert_eq!(suspects, vec![make_addr(0), make_addr(1)]);
+
+		n.get_mut(&make_addr(0))
+			.unwrap()
+			.update_state(NodeState::reincarnate);
+		assert_eq!(n.counts(), (2, 1, 1, 1));
+	}
+
+	#[test]
+	fn node_mut_keeps_indexes_in_sync() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..2 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: NodeState::Alive(1),
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let mut node = n.get_mut(&make_addr(0)).unwrap();
+		node.update_state(NodeState::kill).unwrap();
+		node.set_incarnation(5);
+		node.set_metadata(Some(vec![1].into()));
+		assert_eq!(node.state, NodeState::Alive(5));
+
+		let mut node = n.get_mut(&make_addr(1)).unwrap();
+		node.update_state(NodeState::kill).unwrap();
+		node.set_state(NodeState::Left);
+
+		assert_eq!(n.counts(), (1, 0, 0, 1));
+		assert!(n.dead_since.is_empty());
+		assert_eq!(n.order.len(), 1);
+		assert_eq!(n.get(&make_addr(0)).unwrap().metadata, Some(vec![1].into()));
+	}
+
+	#[test]
+	fn gossip_to_dead() {
+		let rng = StepRng::new(0, 0);
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut n = NodeSet::new(rng).with_clock(clock.clone());
+		let window = Duration::from_secs(30);
+
+		let states = vec![
+			NodeState::Alive(1),
+			NodeState::Suspect(1),
+			NodeState::Dead(1),
+			NodeState::Left,
+		];
+
+		for (i, state) in states.into_iter().enumerate() {
+			n.insert(Node {
+				addr: make_addr(i as u16),
+				state,
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let mut targets = n.gossip_targets(10, window);
+		targets.sort();
+		assert_eq!(targets, vec![make_addr(0), make_addr(1), make_addr(2)]);
+
+		clock.advance(Duration::from_secs(20));
+		n.get_mut(&make_addr(1))
+			.unwrap()
+			.update_state(NodeState::kill)
+			.unwrap();
+		clock.advance(Duration::from_secs(20));
+
+		assert!(!n.is_gossip_target(&make_addr(2), window));
+		assert!(n.is_gossip_target(&make_addr(1), window));
+		assert_eq!(n.gossip_targets(10, window).len(), 2);
+
+		n.get_mut(&make_addr(2))
+			.unwrap()
+			.update_state(NodeState::reincarnate);
+		assert!(n.is_gossip_target(&make_addr(2), window));
+		assert_eq!(n.gossip_targets(1, window).len(), 1);
+	}
+
+	#[test]
+	fn sample_filters_and_excludes() {
+		let rng = StepRng::new(0, 1);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..10 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: if i < 6 {
+					NodeState::Alive(1)
+				} else {
+					NodeState::Dead(1)
+				},
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let probed: HashSet<_> = (0..3).map(|_| n.next_probe_target().unwrap()).collect();
+
+		let exclude = [make_addr(0), make_addr(1)];
+		let mut sample = n.sample(10, &exclude, |n| matches!(n.state, NodeState::Alive(_)));
+		sample.sort();
+
+		assert_eq!(sample, (2..6).map(make_addr).collect::<Vec<_>>());
+		assert_eq!(n.sample(3, &exclude, |_| true).len(), 3);
+		assert!(n.sample(3, &[], |_| false).is_empty());
+
+		// the current round continues with the nodes which have not been probed yet.
+		let rest: HashSet<_> = (0..7).map(|_| n.next_probe_target().unwrap()).collect();
+		assert!(probed.is_disjoint(&rest));
+		assert_eq!(probed.len() + rest.len(), 10);
+	}
+
+	#[test]
+	fn sample_is_uniform() {
+		let rng = SmallRng::seed_from_u64(7);
+		let mut n = NodeSet::new(rng);
+
+		for i in 0..10 {
+			n.insert(Node {
+				addr: make_addr(i),
+				state: NodeState::Alive(1),
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let exclude = [make_addr(0), make_addr(1)];
+		let rounds = 20_000;
+		let mut counts = HashMap::new();
+
+		for _ in 0..rounds {
+			for addr in n.sample(3, &exclude, |_| true) {
+				*counts.entry(addr).or_insert(0) += 1;
+			}
+		}
+
+		// each of the 8 candidates is expected in 3/8 of all rounds.
+		let expected = rounds as f64 * 3.0 / 8.0;
+		assert_eq!(counts.len(), 8);
+		for (addr, count) in counts {
+			let deviation = (count as f64 - expected).abs() / expected;
+			assert!(deviation < 0.05, "{} was sampled {} times", addr, count);
+		}
+	}
+
+	fn insert_returns_correct_result() {
+		let rng = StepRng::new(0, 0);
+		let mut n = NodeSet::new(rng);
+
+		let cases = vec![(0, 3), (1, 2), (3, 2), (2, 0), (3, 1)];
+
+		for (i, result) in cases {
+			let r = n.insert(Node {
+				addr: make_addr(1),
+				state: NodeState::Alive(i),
+				metadata: None,
+				rtt: None,
+			});
+
+			let r = match r {
+				InsertionResult::Unchanged => 0,
+				InsertionResult::Equal(n) if n.state.incarnation().unwrap() == i => 1,
+				InsertionResult::Updated(n) if n.state.incarnation().unwrap() == i => 2,
+				InsertionResult::Inserted(n) if n.state.incarnation().unwrap() == i => 3,
+				_ => 100,
+			};
+
+			assert_eq!(r, result);
+		}
+	}
+}
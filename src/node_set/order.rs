@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// The probe order described in the *SWIM*-paper.
+///
+/// Members are probed round-robin. Each round starts with a new random order. Members which are added
+/// during a round are inserted at a random position among the members which have not been probed yet
+/// in this round, so every member is probed once per round.
+///
+/// The members `order[..cursor]` have been probed in the current round, `order[cursor..]` have not.
+#[derive(Debug, Default)]
+pub(super) struct ProbeOrder {
+	order: Vec<SocketAddr>,
+	positions: HashMap<SocketAddr, usize>,
+	cursor: usize,
+}
+
+impl ProbeOrder {
+	#[inline]
+	pub(super) fn len(&self) -> usize {
+		self.order.len()
+	}
+
+	#[inline]
+	pub(super) fn contains(&self, addr: &SocketAddr) -> bool {
+		self.positions.contains_key(addr)
+	}
+
+	/// Inserts `addr` at a random position among the members which have not been probed yet in the
+	/// current round. Does nothing if `addr` already exists.
+	pub(super) fn insert<R: Rng>(&mut self, addr: SocketAddr, rng: &mut R) {
+		if self.contains(&addr) {
+			return;
+		}
+
+		let last = self.order.len();
+		self.order.push(addr);
+		self.positions.insert(addr, last);
+
+		let i = rng.gen_range(self.cursor..=last);
+		self.swap(i, last);
+	}
+
+	/// Removes `addr` in constant time. Does nothing if `addr` does not exist.
+	pub(super) fn remove(&mut self, addr: &SocketAddr) {
+		let mut i = match self.positions.get(addr) {
+			Some(&i) => i,
+			None => return,
+		};
+
+		// move a probed member to the end of the probed members, which then becomes the first member not
+		// probed yet. The order of the remaining members is unaffected.
+		if i < self.cursor {
+			self.cursor -= 1;
+			self.swap(i, self.cursor);
+			i = self.cursor;
+		}
+
+		self.order.swap_remove(i);
+		self.positions.remove(addr);
+		if let Some(moved) = self.order.get(i) {
+			self.positions.insert(*moved, i);
+		}
+	}
+
+	/// Returns the next member to probe. Starts a new round with a new random order once every member
+	/// has been probed in the current round. Returns [None] if there are no members.
+	pub(super) fn next<R: Rng>(&mut self, rng: &mut R) -> Option<SocketAddr> {
+		if self.order.is_empty() {
+			return None;
+		}
+
+		if self.cursor == self.order.len() {
+			self.order.shuffle(rng);
+			for (i, addr) in self.order.iter().enumerate() {
+				self.positions.insert(*addr, i);
+			}
+			self.cursor = 0;
+		}
+
+		let addr = self.order[self.cursor];
+		self.cursor += 1;
+		Some(addr)
+	}
+
+	fn swap(&mut self, a: usize, b: usize) {
+		self.order.swap(a, b);
+		self.positions.insert(self.order[a], a);
+		self.positions.insert(self.order[b], b);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+	use std::net::{Ipv4Addr, SocketAddrV4};
+
+	use rand::rngs::SmallRng;
+	use rand::SeedableRng;
+
+	use super::*;
+
+	fn make_addr(port: u16) -> SocketAddr {
+		SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port))
+	}
+
+	fn assert_consistent(o: &ProbeOrder) {
+		assert_eq!(o.order.len(), o.positions.len());
+		assert!(o.cursor <= o.order.len());
+		for (i, addr) in o.order.iter().enumerate() {
+			assert_eq!(o.positions[addr], i);
+		}
+	}
+
+	#[test]
+	fn next_returns_none_if_empty() {
+		let mut rng = SmallRng::seed_from_u64(0);
+		let mut o = ProbeOrder::default();
+
+		assert_eq!(o.next(&mut rng), None);
+
+		o.insert(make_addr(1), &mut rng);
+		o.remove(&make_addr(1));
+
+		assert_eq!(o.next(&mut rng), None);
+	}
+
+	#[test]
+	fn every_member_is_probed_once_per_round() {
+		let mut rng = SmallRng::seed_from_u64(1);
+		let mut o = ProbeOrder::default();
+
+		for i in 0..10 {
+			o.insert(make_addr(i), &mut rng);
+		}
+
+		for _ in 0..5 {
+			let round: HashSet<_> = (0..10).map(|_| o.next(&mut rng).unwrap()).collect();
+			assert_eq!(round.len(), 10);
+		}
+	}
+
+	#[test]
+	fn inserted_members_are_probed_in_the_current_round() {
+		let mut rng = SmallRng::seed_from_u64(2);
+
+		for _ in 0..100 {
+			let mut o = ProbeOrder::default();
+			for i in 0..10 {
+				o.insert(make_addr(i), &mut rng);
+			}
+
+			let mut round = HashSet::new();
+			for _ in 0..5 {
+				round.insert(o.next(&mut rng).unwrap());
+			}
+
+			o.insert(make_addr(10), &mut rng);
+			assert_consistent(&o);
+
+			for _ in 0..6 {
+				round.insert(o.next(&mut rng).unwrap());
+			}
+
+			assert_eq!(round.len(), 11);
+			assert!(round.contains(&make_addr(10)));
+		}
+	}
+
+	#[test]
+	fn remove_keeps_the_round() {
+		let mut rng = SmallRng::seed_from_u64(3);
+		let mut o = ProbeOrder::default();
+
+		for i in 0..10 {
+			o.insert(make_addr(i), &mut rng);
+		}
+
+		let probed: Vec<_> = (0..4).map(|_| o.next(&mut rng).unwrap()).collect();
+
+		// remove a probed and an unprobed member
+		o.remove(&probed[1]);
+		let unprobed = o.order[o.cursor + 2];
+		o.remove(&unprobed);
+		o.remove(&make_addr(100));
+		assert_consistent(&o);
+		assert_eq!(o.len(), 8);
+
+		let rest: HashSet<_> = (0..5).map(|_| o.next(&mut rng).unwrap()).collect();
+		let expected: HashSet<_> = (0..10)
+			.map(make_addr)
+			.filter(|a| !probed.contains(a) && *a != unprobed)
+			.collect();
+
+		assert_eq!(rest, expected);
+	}
+}
@@ -2,8 +2,14 @@ use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
 
 use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::clock::{Clock, TokioClock};
+use crate::metrics::{MessageType, Metrics};
 
 #[derive(Debug)]
 pub(crate) enum Ping {
@@ -18,6 +24,8 @@ pub(crate) enum Ping {
 #[derive(Debug)]
 pub(crate) enum FailResult {
 	/// Signals the failure of a direct ping and orders the caller to do an indirect ping.
+	/// If the TCP fallback is enabled, the caller also pings the target via TCP using the same [PingTarget]
+	/// and reports a successful TCP ping using [PingStore::tcp_ack].
 	DoIndirect(PingTarget),
 	/// Signals a `nack`-timeout for a [Ping::Request] and orders the caller to send a nack to the
 	/// node specified in the [RequestSource]. Invoked when 80% of the timeout has passed.
@@ -27,6 +35,9 @@ pub(crate) enum FailResult {
 	/// Signals the failure of an indirect ping. Contains the address of the node which should now be suspected
 	/// and a set of the [SocketAddr] of the nodes which returned nacks.
 	NodeFailed(SocketAddr, HashSet<SocketAddr>),
+	/// Signals the failure of an indirect ping while the TCP fallback ping succeeded.
+	/// The node is reachable, so it must not be suspected, but UDP packets to it seem to get dropped.
+	UdpFailed(SocketAddr),
 }
 
 macro_rules! impl_reqs {
@@ -47,12 +58,44 @@ impl_reqs!(RequestSource);
 #[error("node `{0}` gets currently pinged")]
 pub(crate) struct NodeAlreadyPingedError(SocketAddr);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Error)]
+#[error("node `{0}` has too many ongoing ping requests")]
+pub(crate) struct TooManyRequestsError(SocketAddr);
+
+#[derive(Debug)]
 pub(crate) struct PingStore {
 	sequence: u64,
 	pings: HashMap<u64, Ping>,
 	/// Stores the addresses of the current direct and indirect pings.
 	current: HashSet<SocketAddr>,
+	/// Stores the start time of each direct and indirect ping to measure the round-trip time.
+	started: HashMap<u64, Instant>,
+	/// Stores the `sequence`-numbers of indirect pings whose TCP fallback ping succeeded.
+	tcp_acked: HashSet<u64>,
+	/// Stores the amount of ongoing ping requests of each requesting node.
+	requests: HashMap<SocketAddr, usize>,
+	/// The max amount of ongoing ping requests per requesting node.
+	max_requests: Option<NonZeroUsize>,
+	/// Measures the round-trip times.
+	clock: Arc<dyn Clock>,
+
+	metrics: Arc<Metrics>,
+}
+
+impl Default for PingStore {
+	fn default() -> Self {
+		Self {
+			sequence: 0,
+			pings: HashMap::new(),
+			current: HashSet::new(),
+			started: HashMap::new(),
+			tcp_acked: HashSet::new(),
+			requests: HashMap::new(),
+			max_requests: None,
+			clock: Arc::new(TokioClock),
+			metrics: Arc::default(),
+		}
+	}
 }
 
 impl PingStore {
@@ -60,6 +103,26 @@ impl PingStore {
 		Default::default()
 	}
 
+	pub(crate) fn with_metrics(metrics: Arc<Metrics>) -> Self {
+		Self {
+			metrics,
+			..Default::default()
+		}
+	}
+
+	/// Limits the amount of ongoing ping requests per requesting node, so a single node cannot use
+	/// this node to amplify traffic towards a target.
+	pub(crate) fn with_max_requests(mut self, max_requests: Option<NonZeroUsize>) -> Self {
+		self.max_requests = max_requests;
+		self
+	}
+
+	/// Measures the round-trip times using `clock` instead of the [TokioClock].
+	pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+		self.clock = clock;
+		self
+	}
+
 	/// Returns the current `sequence`-number and increments the counter.
 	fn next_sequence(&mut self) -> u64 {
 		let result = self.sequence;
@@ -86,6 +149,8 @@ impl PingStore {
 		let ping = Ping::Direct(addr);
 
 		self.pings.insert(sequence, ping);
+		self.started.insert(sequence, self.clock.now());
+		self.metrics.probes.inc();
 
 		Ok(PingTarget { sequence, addr })
 	}
@@ -94,7 +159,14 @@ impl PingStore {
 		&mut self,
 		source: RequestSource,
 		target: SocketAddr,
-	) -> PingRequestTarget {
+	) -> Result<PingRequestTarget, TooManyRequestsError> {
+		let requests = self.requests.entry(source.addr).or_insert(0);
+		if matches!(self.max_requests, Some(max) if *requests >= max.get()) {
+			self.metrics.dropped.inc(MessageType::PingRequest);
+			return Err(TooManyRequestsError(source.addr));
+		}
+		*requests += 1;
+
 		let sequence = self.next_sequence();
 		let ping = Ping::Request(source, false);
 
@@ -105,23 +177,45 @@ impl PingStore {
 			addr: target,
 		};
 
-		request
+		Ok(request)
+	}
+
+	/// Decrements the amount of ongoing ping requests of the requesting node.
+	fn finish_request(&mut self, source: &RequestSource) {
+		if let Entry::Occupied(mut entry) = self.requests.entry(source.addr) {
+			*entry.get_mut() -= 1;
+			if *entry.get() == 0 {
+				entry.remove();
+			}
+		}
 	}
 
-	/// Returns [Some] [Ping] for the given `sequence`-number which has been `acked`.
+	/// Returns [Some] [Ping] for the given `sequence`-number which has been `acked`, together with
+	/// the measured round-trip time if the [Ping] was a direct or indirect ping. The round trip of an
+	/// indirect ping includes the nodes which probed the target.
 	///
 	/// Returns [None] if the `sequence`-number has already been `acked` or failed.
-	pub(crate) fn ack(&mut self, sequence: &u64) -> Option<Ping> {
+	pub(crate) fn ack(&mut self, sequence: &u64) -> Option<(Ping, Option<Duration>)> {
 		let ping = self.pings.remove(sequence)?;
 
-		match &ping {
+		let rtt = match &ping {
 			Ping::Direct(addr) | Ping::Indirect(addr, _) => {
 				assert!(self.current.remove(addr));
+				self.metrics.acks.inc();
+
+				self.tcp_acked.remove(sequence);
+				let now = self.clock.now();
+				self.started
+					.remove(sequence)
+					.map(|s| now.saturating_duration_since(s))
 			}
-			_ => {}
-		}
+			Ping::Request(source, _) => {
+				self.finish_request(source);
+				None
+			}
+		};
 
-		Some(ping)
+		Some((ping, rtt))
 	}
 
 	/// Registers a `nack` from a node identified by its [SocketAddr] for a given `sequence`-number and
@@ -131,6 +225,7 @@ impl PingStore {
 		if let Entry::Occupied(mut ping) = self.pings.entry(sequence) {
 			if let Ping::Indirect(_, ref mut nacks) = ping.get_mut() {
 				if nacks.insert(from) {
+					self.metrics.nacks.inc();
 					let count = nacks.len();
 					return NonZeroUsize::new(count); // Always Some.
 				}
@@ -140,6 +235,18 @@ impl PingStore {
 		None
 	}
 
+	/// Registers a successful TCP fallback ping for the indirect ping with the given `sequence`-number.
+	/// Returns `false` if there is no such indirect ping.
+	pub(crate) fn tcp_ack(&mut self, sequence: u64) -> bool {
+		match self.pings.get(&sequence) {
+			Some(Ping::Indirect(_, _)) => {
+				self.tcp_acked.insert(sequence);
+				true
+			}
+			_ => false,
+		}
+	}
+
 	/// Returns [Some] amount of `nacks` recived for a given `sequence`-number.
 	///
 	/// [None] will be returned if the `sequence`-number cannot be found, or the ping is not an indirect ping.
@@ -155,7 +262,10 @@ impl PingStore {
 		let ping = self.pings.remove(&sequence)?;
 
 		match ping {
-			Ping::Request(source, true) => Some(FailResult::RequestFailed(source)),
+			Ping::Request(source, true) => {
+				self.finish_request(&source);
+				Some(FailResult::RequestFailed(source))
+			}
 			Ping::Request(source, false) => {
 				let ping = Ping::Request(source, true);
 
@@ -164,16 +274,26 @@ impl PingStore {
 				Some(FailResult::SendNack(source))
 			}
 			Ping::Direct(addr) => {
+				self.started.remove(&sequence);
+
 				let sequence = self.next_sequence();
 				let ping = Ping::Indirect(addr, HashSet::new());
 				self.pings.insert(sequence, ping);
+				self.started.insert(sequence, self.clock.now());
+				self.metrics.indirect_probes.inc();
 
 				let target = PingTarget { addr, sequence };
 				Some(FailResult::DoIndirect(target))
 			}
 			Ping::Indirect(addr, nacks) => {
 				assert!(self.current.remove(&addr));
-				Some(FailResult::NodeFailed(addr, nacks))
+				self.started.remove(&sequence);
+
+				if self.tcp_acked.remove(&sequence) {
+					Some(FailResult::UdpFailed(addr))
+				} else {
+					Some(FailResult::NodeFailed(addr, nacks))
+				}
 			}
 		}
 	}
@@ -182,6 +302,9 @@ impl PingStore {
 	pub(crate) fn clear(&mut self) {
 		self.pings.clear();
 		self.current.clear();
+		self.started.clear();
+		self.tcp_acked.clear();
+		self.requests.clear();
 	}
 
 	/// Returns the currently ongoing pings in the order of:
@@ -204,6 +327,7 @@ mod tests {
 	use std::convert::TryInto;
 
 	use super::*;
+	use crate::clock::ManualClock;
 
 	fn addr(port: u16) -> SocketAddr {
 		format!("127.0.0.1:{}", port).parse().unwrap()
@@ -298,6 +422,25 @@ mod tests {
 		assert_eq!(p.pingcounts(), (1, 0, 0));
 	}
 
+	#[test]
+	fn tcp_fallback() {
+		let mut p = PingStore::new();
+
+		let target = p.ping(addr(1)).unwrap();
+		assert!(!p.tcp_ack(target.sequence));
+
+		let target = match p.fail(target.sequence).unwrap() {
+			FailResult::DoIndirect(target) => target,
+			_ => unreachable!(),
+		};
+		assert!(p.tcp_ack(target.sequence));
+
+		let result = p.fail(target.sequence).unwrap();
+		assert!(matches!(result, FailResult::UdpFailed(a) if a == addr(1)));
+		assert!(p.tcp_acked.is_empty());
+		assert_eq!(p.pingcounts(), (0, 0, 0));
+	}
+
 	#[test]
 	fn ping_req_and_fail() {
 		let mut p = PingStore::new();
@@ -308,7 +451,8 @@ mod tests {
 				addr: addr(1),
 			},
 			addr(100),
-		);
+		)
+		.unwrap();
 
 		assert_eq!(p.pingcounts(), (0, 0, 1));
 
@@ -322,4 +466,65 @@ mod tests {
 
 		assert!(p.fail(0).is_none())
 	}
+
+	#[test]
+	fn max_requests() {
+		let mut p = PingStore::new().with_max_requests(NonZeroUsize::new(2));
+
+		let source = |sequence| RequestSource {
+			sequence,
+			addr: addr(1),
+		};
+
+		let first = p.ping_request(source(0), addr(100)).unwrap();
+		p.ping_request(source(1), addr(100)).unwrap();
+		assert!(p.ping_request(source(2), addr(100)).is_err());
+		assert_eq!(p.metrics.dropped.get(MessageType::PingRequest).get(), 1);
+
+		let other = RequestSource {
+			sequence: 0,
+			addr: addr(2),
+		};
+		assert!(p.ping_request(other, addr(100)).is_ok());
+
+		assert!(p.ack(&first.sequence).is_some());
+		assert!(p.ping_request(source(3), addr(100)).is_ok());
+	}
+
+	#[test]
+	fn ack_measures_rtt() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = PingStore::new().with_clock(clock.clone());
+
+		let target = p.ping(addr(1)).unwrap();
+		clock.advance(Duration::from_millis(30));
+
+		let (ping, rtt) = p.ack(&target.sequence).unwrap();
+		assert!(matches!(ping, Ping::Direct(a) if a == addr(1)));
+		assert_eq!(rtt, Some(Duration::from_millis(30)));
+
+		let target = p.ping(addr(1)).unwrap();
+		clock.advance(Duration::from_millis(500));
+
+		let result = p.fail(target.sequence).unwrap();
+		let target = match result {
+			FailResult::DoIndirect(target) => target,
+			_ => unreachable!(),
+		};
+		clock.advance(Duration::from_millis(80));
+
+		// the round trip through other nodes starts with the indirect ping.
+		let (ping, rtt) = p.ack(&target.sequence).unwrap();
+		assert!(matches!(ping, Ping::Indirect(a, _) if a == addr(1)));
+		assert_eq!(rtt, Some(Duration::from_millis(80)));
+		assert!(p.started.is_empty());
+
+		let target = p.ping(addr(1)).unwrap();
+		let target = match p.fail(target.sequence).unwrap() {
+			FailResult::DoIndirect(target) => target,
+			_ => unreachable!(),
+		};
+		p.fail(target.sequence);
+		assert!(p.started.is_empty());
+	}
 }
@@ -0,0 +1,117 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::coordinate::Coordinate;
+use crate::{EventHandler, Node};
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Returns the [Coordinate] of this node, which is piggybacked on each `ack`, or [None] if
+	/// coordinates are disabled.
+	#[inline]
+	pub(crate) fn coordinate(&self) -> Option<&Coordinate> {
+		self.vivaldi.as_ref().map(|vivaldi| vivaldi.coordinate())
+	}
+
+	/// Returns the estimated round-trip time to `addr`, if its [Coordinate] is known.
+	pub(crate) fn distance_to(&self, addr: &SocketAddr) -> Option<Duration> {
+		self.vivaldi.as_ref()?.distance_to(addr)
+	}
+
+	/// Returns all members sorted by their estimated round-trip time, closest first.
+	/// Members without a known [Coordinate] are moved to the end.
+	pub(crate) fn members_by_distance(&self) -> Vec<&Node> {
+		let mut addrs: Vec<SocketAddr> = self.nodes.get_map().keys().copied().collect();
+		if let Some(vivaldi) = self.vivaldi.as_ref() {
+			vivaldi.sort_by_distance(&mut addrs);
+		}
+
+		addrs
+			.iter()
+			.filter_map(|addr| self.nodes.get(addr))
+			.collect()
+	}
+
+	/// Updates the local [Coordinate] with the `coordinate` of `addr` and the measured round-trip
+	/// time to it.
+	pub(super) fn update_coordinate(
+		&mut self,
+		addr: SocketAddr,
+		coordinate: Coordinate,
+		rtt: Duration,
+	) {
+		if let Some(vivaldi) = self.vivaldi.as_mut() {
+			vivaldi.update(addr, coordinate, rtt);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::num::NonZeroUsize;
+	use std::sync::Arc;
+
+	use tokio::time::Instant;
+
+	use super::*;
+	use crate::clock::ManualClock;
+	use crate::protocol::tests::{addr, protocol, protocol_with};
+	use crate::CoordinateConfig;
+
+	fn config() -> CoordinateConfig {
+		CoordinateConfig {
+			dimensionality: NonZeroUsize::new(2).unwrap(),
+			..CoordinateConfig::default()
+		}
+	}
+
+	fn at(x: f64) -> Coordinate {
+		let mut coordinate = Coordinate::new(&config());
+		coordinate.vec[0] = x;
+		coordinate
+	}
+
+	#[tokio::test]
+	async fn coordinates_are_disabled_by_default() {
+		let mut p = protocol();
+
+		let target = p.probe(addr(1)).unwrap();
+		p.ack(target.sequence, Some(at(0.1)));
+
+		assert!(p.coordinate().is_none());
+		assert_eq!(p.distance_to(&addr(1)), None);
+		assert_eq!(p.members_by_distance().len(), 4);
+	}
+
+	#[tokio::test]
+	async fn acks_update_the_coordinates() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = protocol_with(None, clock.clone()).with_coordinates(Some(config()));
+
+		for (port, x) in [(1, 0.3), (2, 0.1), (3, 0.2)].iter() {
+			let target = p.probe(addr(*port)).unwrap();
+			clock.advance(Duration::from_millis(10));
+			p.ack(target.sequence, Some(at(*x)));
+		}
+
+		// the coordinate of an incompatible node is ignored.
+		let target = p.probe(addr(4)).unwrap();
+		p.ack(
+			target.sequence,
+			Some(Coordinate::new(&CoordinateConfig::default())),
+		);
+
+		assert_ne!(p.coordinate(), Some(&Coordinate::new(&config())));
+		assert!(p.distance_to(&addr(1)).is_some());
+		assert_eq!(p.distance_to(&addr(4)), None);
+
+		let members: Vec<_> = p.members_by_distance().iter().map(|n| n.addr).collect();
+		assert_eq!(members, vec![addr(2), addr(3), addr(1), addr(4)]);
+	}
+}
@@ -0,0 +1,146 @@
+use std::io;
+use std::net::SocketAddr;
+
+use rand::Rng;
+use tokio::time::Instant;
+
+use super::Protocol;
+use crate::{EventHandler, MessageType};
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Records a message of `len` bytes received from `from`. Returns `false` if the message exceeds
+	/// the rate limit and must be dropped.
+	pub(crate) fn received(&mut self, from: SocketAddr, kind: MessageType, len: usize) -> bool {
+		self.metrics.bytes_in.add(kind, len);
+
+		match self.limiter.as_mut() {
+			Some(limiter) => limiter.check(from, kind),
+			None => true,
+		}
+	}
+
+	/// Records a sent message of `len` bytes.
+	pub(crate) fn sent(&mut self, kind: MessageType, len: usize) {
+		self.metrics.bytes_out.add(kind, len);
+	}
+
+	/// Starts a push-pull sync with `addr`. Returns the time the sync started, which must be passed
+	/// to [Protocol::synced].
+	pub(crate) fn sync(&mut self, addr: &SocketAddr) -> Instant {
+		self.handler.sync(addr);
+		self.clock.now()
+	}
+
+	/// Records the duration and the result of a push-pull sync with `addr`.
+	pub(crate) fn synced(&mut self, addr: &SocketAddr, started: Instant, result: io::Result<()>) {
+		let now = self.clock.now();
+		self.metrics
+			.push_pull_duration
+			.observe(now.saturating_duration_since(started));
+
+		if let Err(e) = result {
+			self.handler.sync_failed(addr, e);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::num::{NonZeroU32, NonZeroUsize};
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	use super::*;
+	use crate::clock::ManualClock;
+	use crate::ping::RequestSource;
+	use crate::protocol::tests::{addr, protocol, protocol_with};
+	use crate::{RateLimit, RateLimitConfig};
+
+	#[tokio::test]
+	async fn messages_are_counted() {
+		let mut p = protocol();
+
+		assert!(p.received(addr(1), MessageType::Ping, 20));
+		assert!(p.received(addr(1), MessageType::Ping, 30));
+		p.sent(MessageType::Ack, 40);
+
+		assert_eq!(p.metrics.bytes_in.get(MessageType::Ping).get(), 50);
+		assert_eq!(p.metrics.bytes_in.get(MessageType::Ack).get(), 0);
+		assert_eq!(p.metrics.bytes_out.get(MessageType::Ack).get(), 40);
+	}
+
+	#[tokio::test]
+	async fn messages_are_rate_limited() {
+		let config = RateLimitConfig {
+			ping: Some(RateLimit {
+				rate: 1.0,
+				burst: NonZeroU32::new(2).unwrap(),
+			}),
+			max_ping_requests: NonZeroUsize::new(1),
+			..RateLimitConfig::default()
+		};
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = protocol_with(None, clock.clone())
+			.with_rate_limit(config)
+			.unwrap();
+
+		assert!(p.received(addr(1), MessageType::Ping, 10));
+		assert!(p.received(addr(1), MessageType::Ping, 10));
+		assert!(!p.received(addr(1), MessageType::Ping, 10));
+		// ports are ignored, other hosts have their own limit.
+		assert!(!p.received(addr(2), MessageType::Ping, 10));
+		let other = "127.0.0.2:1".parse().unwrap();
+		assert!(p.received(other, MessageType::Ping, 10));
+		assert!(p.received(addr(1), MessageType::Ack, 10));
+
+		clock.advance(Duration::from_secs(1));
+		assert!(p.received(addr(1), MessageType::Ping, 10));
+		assert_eq!(p.metrics.dropped.get(MessageType::Ping).get(), 2);
+
+		let source = |sequence| RequestSource {
+			sequence,
+			addr: addr(1),
+		};
+		assert!(p.ping_request(source(0), addr(2)).is_ok());
+		assert!(p.ping_request(source(1), addr(3)).is_err());
+		assert_eq!(p.metrics.dropped.get(MessageType::PingRequest).get(), 1);
+	}
+
+	#[tokio::test]
+	async fn invalid_rate_limits_are_rejected() {
+		let config = RateLimitConfig {
+			suspect: Some(RateLimit {
+				rate: 0.0,
+				burst: NonZeroU32::new(1).unwrap(),
+			}),
+			..RateLimitConfig::default()
+		};
+
+		assert!(protocol().with_rate_limit(config).is_err());
+	}
+
+	#[tokio::test]
+	async fn syncs_are_timed() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = protocol_with(None, clock.clone());
+
+		let started = p.sync(&addr(1));
+		clock.advance(Duration::from_millis(30));
+		p.synced(&addr(1), started, Ok(()));
+
+		let started = p.sync(&addr(2));
+		clock.advance(Duration::from_millis(10));
+		p.synced(&addr(2), started, Err(io::ErrorKind::TimedOut.into()));
+
+		assert_eq!(p.metrics.push_pull_duration.count(), 2);
+		assert_eq!(
+			p.metrics.push_pull_duration.sum(),
+			Duration::from_millis(40)
+		);
+		assert_eq!(p.handler.sync_failures, vec![addr(2)]);
+	}
+}
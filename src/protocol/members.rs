@@ -0,0 +1,227 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::node_set::InsertionResult;
+use crate::{Cause, EventHandler, Node, NodeState};
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Restores the stored [Snapshot](crate::snapshot::Snapshot), stores the current one and starts
+	/// the rejoin attempts if the node is isolated. Called once before the protocol loop starts.
+	///
+	/// Returns the configured `seeds` extended by the peers of the restored snapshot.
+	pub(crate) fn start(&mut self, seeds: &[String]) -> Box<[String]> {
+		let seeds = self.restore_snapshot(seeds);
+		self.membership_changed();
+		seeds
+	}
+
+	/// Applies the state of another node received in an `alive`, `dead` or push-pull message.
+	/// A newer state which is not [NodeState::Suspect] ends the suspicion about the node.
+	///
+	/// Returns `true` if the state is new and must be gossiped further.
+	pub(crate) fn update_node(&mut self, node: Node) -> bool {
+		let addr = node.addr;
+
+		let suspected = match self.nodes.insert(node) {
+			InsertionResult::Inserted(node) | InsertionResult::Updated(node) => {
+				self.handler.node(node, Cause::Update);
+				matches!(node.state, NodeState::Suspect(_))
+			}
+			InsertionResult::Equal(_) | InsertionResult::Unchanged => return false,
+		};
+
+		if !suspected {
+			self.end_suspicion(&addr);
+		}
+		self.membership_changed();
+
+		true
+	}
+
+	/// Removes a node which has been dead or left for long enough.
+	pub(crate) fn remove_node(&mut self, addr: &SocketAddr) -> Option<Node> {
+		let node = self.nodes.remove(addr)?;
+
+		self.end_suspicion(addr);
+		if let Some(vivaldi) = self.vivaldi.as_mut() {
+			vivaldi.forget(addr);
+		}
+		self.handler.removed(node.clone());
+		self.membership_changed();
+
+		Some(node)
+	}
+
+	/// Handles a [SchedulerEvent::ReconnectInterval](crate::scheduler::SchedulerEvent::ReconnectInterval).
+	/// Returns a random dead node, which should be contacted to heal a partition.
+	pub(crate) fn reconnect(&mut self) -> Option<SocketAddr> {
+		self.nodes.random_dead_addr()
+	}
+
+	/// Schedules the next attempt to rejoin the cluster after the last one failed.
+	pub(crate) fn rejoin_failed(&mut self) {
+		self.scheduler.rejoin_failed();
+	}
+
+	/// Starts or stops the rejoin attempts, rescales the timers and stores the [Snapshot] after the
+	/// state of a node changed.
+	///
+	/// [Snapshot]: crate::snapshot::Snapshot
+	pub(super) fn membership_changed(&mut self) {
+		let isolation = self.nodes.isolation(&self.addr);
+		self.scheduler.update_isolation(isolation);
+
+		let local = usize::from(!self.nodes.contains(&self.addr));
+		if let Some(node_count) = NonZeroUsize::new(self.nodes.len() + local) {
+			self.scheduler.update_node_count(node_count);
+		}
+
+		self.store_snapshot();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	use tokio::time::Instant;
+
+	use super::*;
+	use crate::clock::ManualClock;
+	use crate::protocol::tests::{addr, protocol, protocol_with_events};
+	use crate::scheduler::tests::advance;
+	use crate::scheduler::SchedulerEvent;
+
+	fn node(port: u16, state: NodeState) -> Node {
+		Node {
+			addr: addr(port),
+			state,
+			metadata: None,
+			rtt: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn newer_states_are_applied() {
+		let mut p = protocol();
+
+		assert!(!p.update_node(node(1, NodeState::Alive(1))));
+		assert!(!p.update_node(node(1, NodeState::Alive(0))));
+		assert!(p.update_node(node(1, NodeState::Dead(1))));
+		assert!(p.update_node(node(5, NodeState::Alive(0))));
+
+		assert_eq!(p.nodes.get(&addr(1)).unwrap().state, NodeState::Dead(1));
+		assert_eq!(
+			p.handler.nodes,
+			vec![
+				(addr(1), NodeState::Dead(1), "update"),
+				(addr(5), NodeState::Alive(0), "update"),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn removed_nodes_are_forgotten() {
+		let mut p = protocol();
+
+		assert!(p.suspect(addr(1), 1, addr(2)));
+		assert_eq!(p.remove_node(&addr(1)).unwrap().addr, addr(1));
+		assert!(p.remove_node(&addr(1)).is_none());
+
+		assert!(p.nodes.get(&addr(1)).is_none());
+		assert!(p.suspicions().is_empty());
+		assert_eq!(p.handler.removed, vec![addr(1)]);
+	}
+
+	#[tokio::test]
+	async fn dead_nodes_are_reconnected() {
+		let mut p = protocol();
+		assert_eq!(p.reconnect(), None);
+
+		p.update_node(node(3, NodeState::Dead(1)));
+		assert_eq!(p.reconnect(), Some(addr(3)));
+	}
+
+	#[tokio::test]
+	async fn suspicion_timeouts_scale_with_the_cluster() {
+		let mut p = protocol();
+
+		assert!(p.suspect(addr(1), 1, addr(2)));
+		let small = p.scheduler.suspicion_timeout(&addr(1)).unwrap().0;
+
+		for port in 5..=100 {
+			p.update_node(node(port, NodeState::Alive(0)));
+		}
+		let large = p.scheduler.suspicion_timeout(&addr(1)).unwrap().0;
+		assert!(large > small, "{:?} <= {:?}", large, small);
+	}
+
+	#[tokio::test]
+	async fn lonely_nodes_rejoin() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let (mut events, mut p) = protocol_with_events(None, clock.clone());
+
+		let rejoined = |events: &[SchedulerEvent]| {
+			events
+				.iter()
+				.any(|e| matches!(e, SchedulerEvent::RejoinTimeout))
+		};
+
+		assert!(p.start(&[]).is_empty());
+		assert!(!rejoined(
+			&advance(&clock, &mut events, Duration::from_secs(20)).await
+		));
+
+		for port in 1..=4 {
+			p.remove_node(&addr(port));
+		}
+		assert!(!rejoined(
+			&advance(&clock, &mut events, Duration::from_secs(9)).await
+		));
+		assert!(rejoined(
+			&advance(&clock, &mut events, Duration::from_secs(1)).await
+		));
+	}
+
+	#[tokio::test]
+	async fn isolated_nodes_rejoin() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let (mut events, mut p) = protocol_with_events(None, clock.clone());
+
+		let rejoins = |events: &[SchedulerEvent]| {
+			events
+				.iter()
+				.filter(|e| matches!(e, SchedulerEvent::RejoinTimeout))
+				.count()
+		};
+
+		for port in 1..=3 {
+			p.update_node(node(port, NodeState::Dead(1)));
+		}
+		let ready = advance(&clock, &mut events, Duration::from_millis(1)).await;
+		assert_eq!(rejoins(&ready), 0);
+
+		// every peer is down, so the first attempt is made right away.
+		p.update_node(node(4, NodeState::Left));
+		let ready = advance(&clock, &mut events, Duration::from_millis(1)).await;
+		assert_eq!(rejoins(&ready), 1);
+
+		p.rejoin_failed();
+		let ready = advance(&clock, &mut events, Duration::from_millis(1100)).await;
+		assert_eq!(rejoins(&ready), 1);
+
+		// a rejoined node stops the attempts.
+		p.rejoin_failed();
+		p.update_node(node(1, NodeState::Alive(2)));
+		let ready = advance(&clock, &mut events, Duration::from_secs(10)).await;
+		assert_eq!(rejoins(&ready), 0);
+	}
+}
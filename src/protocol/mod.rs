@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::awareness::{Awareness, Health, HealthEvent};
+use crate::clock::Clock;
+use crate::coordinate::Vivaldi;
+use crate::metrics::Metrics;
+use crate::node_set::NodeSet;
+use crate::ping::PingStore;
+use crate::rate_limit::{RateLimitError, RateLimiter};
+use crate::scheduler::Scheduler;
+use crate::snapshot::Snapshot;
+use crate::suspicions::Suspicions;
+use crate::{AwarenessConfig, CoordinateConfig, EventHandler, RateLimitConfig, Reconfiguration};
+
+mod coordinates;
+mod io;
+mod members;
+mod probe;
+mod refute;
+mod state;
+mod suspicion;
+
+/// The failure detector of a node.
+///
+/// Ties the outcome of each probe to the member list, the timers of the [Scheduler], the local
+/// health and the [EventHandler]. The protocol loop sends the messages and calls the matching
+/// method for each received message and each [SchedulerEvent](crate::scheduler::SchedulerEvent).
+pub(crate) struct Protocol<E, R> {
+	/// The address of this node.
+	addr: SocketAddr,
+	incarnation: u64,
+
+	nodes: NodeSet<R>,
+	pings: PingStore,
+	/// The amount of nodes which were asked to probe the target of each indirect probe.
+	expected_nacks: HashMap<u64, usize>,
+	/// The active suspicions about other nodes.
+	suspicions: Suspicions,
+	/// Whether the target of each indirect probe is also pinged via TCP.
+	tcp_fallback: bool,
+
+	/// Limits the rate of incoming messages, if configured.
+	limiter: Option<RateLimiter>,
+	/// The network coordinates, if enabled.
+	vivaldi: Option<Vivaldi>,
+
+	scheduler: Scheduler,
+	awareness: Awareness,
+	handler: E,
+
+	metrics: Arc<Metrics>,
+	clock: Arc<dyn Clock>,
+
+	/// The directory the [Snapshot] is stored in.
+	state_dir: Option<PathBuf>,
+	/// The last stored [Snapshot], so unchanged snapshots are not written again.
+	stored: Option<Snapshot>,
+}
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Returns a new [Protocol]. The member list, the round-trip times and the health history follow
+	/// the clock of the [Scheduler].
+	pub(crate) fn new(
+		addr: SocketAddr,
+		incarnation: u64,
+		nodes: NodeSet<R>,
+		scheduler: Scheduler,
+		awareness: &AwarenessConfig,
+		handler: E,
+		metrics: Arc<Metrics>,
+	) -> Self {
+		let clock = scheduler.clock();
+
+		Self {
+			addr,
+			incarnation,
+			nodes: nodes.with_clock(clock.clone()),
+			pings: PingStore::with_metrics(metrics.clone()).with_clock(clock.clone()),
+			expected_nacks: HashMap::new(),
+			suspicions: Suspicions::new(clock.clone()),
+			tcp_fallback: false,
+			limiter: None,
+			vivaldi: None,
+			scheduler,
+			awareness: Awareness::from_config(awareness).with_clock(clock.clone()),
+			handler,
+			metrics,
+			clock,
+			state_dir: None,
+			stored: None,
+		}
+	}
+
+	/// Restores the [Snapshot] in `state_dir` at startup and stores it whenever the incarnation number
+	/// or the members change.
+	pub(crate) fn with_state_dir(mut self, state_dir: Option<PathBuf>) -> Self {
+		self.state_dir = state_dir;
+		self
+	}
+
+	/// Pings the target of each indirect probe via TCP as well if `tcp_fallback` is `true`.
+	/// See [PingConfig::tcp_fallback](crate::PingConfig::tcp_fallback).
+	pub(crate) fn with_tcp_fallback(mut self, tcp_fallback: bool) -> Self {
+		self.tcp_fallback = tcp_fallback;
+		self
+	}
+
+	/// Limits the rate of incoming messages and the amount of ongoing ping requests per node.
+	/// Fails if a rate of the config is not positive.
+	pub(crate) fn with_rate_limit(
+		mut self,
+		config: RateLimitConfig,
+	) -> Result<Self, RateLimitError> {
+		self.pings = self.pings.with_max_requests(config.max_ping_requests);
+		self.limiter = Some(RateLimiter::new(
+			config,
+			self.clock.clone(),
+			self.metrics.clone(),
+		)?);
+		Ok(self)
+	}
+
+	/// Maintains the network coordinates of the local node if `config` is [Some].
+	pub(crate) fn with_coordinates(mut self, config: Option<CoordinateConfig>) -> Self {
+		self.vivaldi = config.map(Vivaldi::from_entropy);
+		self
+	}
+
+	/// Returns `true` if the target of each indirect probe must also be pinged via TCP, whose `ack`
+	/// is reported using [Protocol::tcp_ack].
+	#[inline]
+	pub(crate) fn tcp_fallback(&self) -> bool {
+		self.tcp_fallback
+	}
+
+	/// Applies new timing parameters to the running node. Running timers and intervals are rebuilt
+	/// using the new parameters.
+	pub(crate) fn reconfigure(&mut self, config: Reconfiguration) {
+		self.scheduler.reconfigure(config);
+	}
+
+	/// Returns the local health of this node.
+	pub(crate) fn health(&self) -> Health {
+		self.awareness.health()
+	}
+
+	/// Returns the current incarnation number of this node.
+	#[inline]
+	pub(crate) fn incarnation(&self) -> u64 {
+		self.incarnation
+	}
+
+	fn update_health(&mut self, event: HealthEvent) {
+		self.scheduler
+			.update_health(&mut self.awareness, event, &mut self.handler);
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::net::SocketAddr;
+	use std::num::{NonZeroU32, NonZeroUsize};
+	use std::time::Duration;
+
+	use rand::rngs::SmallRng;
+	use rand::SeedableRng;
+	use tokio::time::Instant;
+
+	use super::*;
+	use crate::awareness::HealthChangeReason;
+	use crate::clock::ManualClock;
+	use crate::scheduler::tests::config;
+	use crate::scheduler::SchedulerEvents;
+	use crate::{AdaptiveTimeoutConfig, Cause, Node, NodeState, Rtt};
+
+	#[derive(Default)]
+	pub(crate) struct Recorder {
+		pub(crate) awareness: Vec<u32>,
+		pub(crate) acks: Vec<SocketAddr>,
+		pub(crate) rtts: Vec<(SocketAddr, Duration)>,
+		pub(crate) suspected: Vec<SocketAddr>,
+		pub(crate) snapshot_failures: usize,
+		pub(crate) sync_failures: Vec<SocketAddr>,
+		pub(crate) udp_unreachable: Vec<SocketAddr>,
+		pub(crate) nodes: Vec<(SocketAddr, NodeState, &'static str)>,
+		pub(crate) removed: Vec<SocketAddr>,
+	}
+
+	impl EventHandler for Recorder {
+		fn awareness(&mut self, awareness: NonZeroU32, _: NonZeroU32) {
+			self.awareness.push(awareness.get());
+		}
+
+		fn node(&mut self, node: &Node, cause: Cause) {
+			let cause = match cause {
+				Cause::Update => "update",
+				Cause::Suspicion => "suspicion",
+				Cause::Death => "death",
+			};
+			self.nodes.push((node.addr, node.state.clone(), cause));
+		}
+
+		fn removed(&mut self, node: Node) {
+			self.removed.push(node.addr);
+		}
+
+		fn ack(&mut self, target: &SocketAddr) {
+			self.acks.push(*target);
+		}
+
+		fn rtt(&mut self, addr: &SocketAddr, rtt: &Rtt) {
+			self.rtts.push((*addr, rtt.srtt));
+		}
+
+		fn suspected(&mut self, suspector: &SocketAddr) {
+			self.suspected.push(*suspector);
+		}
+
+		fn snapshot_failed(&mut self, _: std::io::Error) {
+			self.snapshot_failures += 1;
+		}
+
+		fn udp_unreachable(&mut self, addr: &SocketAddr) {
+			self.udp_unreachable.push(*addr);
+		}
+
+		fn sync_failed(&mut self, addr: &SocketAddr, _: std::io::Error) {
+			self.sync_failures.push(*addr);
+		}
+	}
+
+	pub(crate) fn addr(port: u16) -> SocketAddr {
+		format!("127.0.0.1:{}", port).parse().unwrap()
+	}
+
+	pub(crate) fn protocol() -> Protocol<Recorder, SmallRng> {
+		protocol_with(None, Arc::new(ManualClock::new(Instant::now())))
+	}
+
+	pub(crate) fn protocol_with(
+		adaptive: Option<AdaptiveTimeoutConfig>,
+		clock: Arc<ManualClock>,
+	) -> Protocol<Recorder, SmallRng> {
+		protocol_with_events(adaptive, clock).1
+	}
+
+	/// Returns a [Protocol] at `addr(0)` which knows the alive nodes `addr(1)` to `addr(4)`, together
+	/// with the events of its [Scheduler].
+	pub(crate) fn protocol_with_events(
+		adaptive: Option<AdaptiveTimeoutConfig>,
+		clock: Arc<ManualClock>,
+	) -> (SchedulerEvents, Protocol<Recorder, SmallRng>) {
+		let metrics = Arc::new(Metrics::default());
+		let mut rng = SmallRng::seed_from_u64(0);
+
+		let mut config = config(clock);
+		config.ping.adaptive_timeout = adaptive;
+
+		let (events, scheduler) = Scheduler::new(
+			config,
+			NonZeroUsize::new(1).unwrap(),
+			metrics.clone(),
+			&mut rng,
+		);
+
+		let mut nodes = NodeSet::new(rng);
+		for port in 1..=4 {
+			nodes.insert(Node {
+				addr: addr(port),
+				state: NodeState::Alive(1),
+				metadata: None,
+				rtt: None,
+			});
+		}
+
+		let protocol = Protocol::new(
+			addr(0),
+			0,
+			nodes,
+			scheduler,
+			&AwarenessConfig {
+				max: NonZeroU32::new(8).unwrap(),
+				history_len: 4,
+			},
+			Recorder::default(),
+			metrics,
+		);
+
+		(events, protocol)
+	}
+
+	#[tokio::test]
+	async fn health_follows_the_clock() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let start = clock.now();
+		let mut p = protocol_with(None, clock.clone());
+
+		p.update_health(HealthEvent::Refuted);
+		clock.advance(Duration::from_secs(2));
+		p.update_health(HealthEvent::ProbeFailed {
+			expected_nacks: 0,
+			nacks: 0,
+		});
+
+		let health = p.health();
+		assert_eq!(health.score.get(), 3);
+		assert_eq!(health.max.get(), 8);
+
+		let history: Vec<_> = health
+			.history
+			.iter()
+			.map(|c| (c.at - start, c.reason))
+			.collect();
+		assert_eq!(
+			history,
+			vec![
+				(Duration::ZERO, HealthChangeReason::Refutation),
+				(Duration::from_secs(2), HealthChangeReason::FailedProbe),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn reconfiguration_changes_the_timers() {
+		let mut p = protocol();
+
+		p.reconfigure(Reconfiguration {
+			ping_timeout: Some(Duration::from_millis(50)),
+			..Reconfiguration::default()
+		});
+
+		let target = p.probe(addr(1)).unwrap();
+		assert_eq!(
+			p.scheduler.ping_timeout(target.sequence),
+			Some(Duration::from_millis(50))
+		);
+	}
+}
@@ -0,0 +1,322 @@
+use std::net::SocketAddr;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::awareness::HealthEvent;
+use crate::coordinate::Coordinate;
+use crate::ping::{
+	FailResult, NodeAlreadyPingedError, Ping, PingRequestTarget, PingTarget, RequestSource,
+	TooManyRequestsError,
+};
+use crate::EventHandler;
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Starts a direct probe of `addr` and its timer, which uses the [Rtt](crate::Rtt) of `addr`.
+	pub(crate) fn probe(&mut self, addr: SocketAddr) -> Result<PingTarget, NodeAlreadyPingedError> {
+		let target = self.pings.ping(addr)?;
+
+		let rtt = self.nodes.get(&addr).and_then(|node| node.rtt);
+		self.scheduler.start_ping_timer(target.sequence, rtt);
+		self.handler.ping(&addr);
+
+		Ok(target)
+	}
+
+	/// Registers the nodes which were asked to probe the target of an indirect probe, which has been
+	/// returned by [Protocol::ping_timeout] as [FailResult::DoIndirect].
+	pub(crate) fn indirect_probe(&mut self, target: &PingTarget, executors: &[SocketAddr]) {
+		self.expected_nacks.insert(target.sequence, executors.len());
+		self.handler.indirect_ping(&target.addr, executors);
+	}
+
+	/// Starts a ping requested by another node and the timer after which a `nack` is sent back.
+	pub(crate) fn ping_request(
+		&mut self,
+		source: RequestSource,
+		target: SocketAddr,
+	) -> Result<PingRequestTarget, TooManyRequestsError> {
+		let request = self.pings.ping_request(source, target)?;
+
+		let rtt = self.nodes.get(&target).and_then(|node| node.rtt);
+		self.scheduler.start_nack_timer(request.sequence, rtt);
+		self.handler.ping_request(&target, &source.addr);
+
+		Ok(request)
+	}
+
+	/// Handles an `ack` and returns the acked [Ping]. A successful probe lowers the awareness score and
+	/// a direct probe updates the round-trip time of the target and the local [Coordinate] with the
+	/// `coordinate` piggybacked on the `ack`. The round-trip times of indirect probes are only
+	/// recorded in [Metrics::indirect_probe_rtt](crate::Metrics::indirect_probe_rtt).
+	/// Returns [None] if the ping has already been acked or failed.
+	pub(crate) fn ack(&mut self, sequence: u64, coordinate: Option<Coordinate>) -> Option<Ping> {
+		let (ping, sample) = self.pings.ack(&sequence)?;
+		self.scheduler.stop_ping_timer(sequence);
+
+		match &ping {
+			Ping::Direct(addr) | Ping::Indirect(addr, _) => {
+				self.expected_nacks.remove(&sequence);
+				self.handler.ack(addr);
+
+				match (&ping, sample) {
+					(Ping::Direct(_), Some(sample)) => {
+						self.metrics.probe_rtt.observe(sample);
+						if let Some(rtt) = self.nodes.update_rtt(addr, sample) {
+							self.handler.rtt(addr, rtt);
+						}
+						if let Some(coordinate) = coordinate {
+							self.update_coordinate(*addr, coordinate, sample);
+						}
+					}
+					(Ping::Indirect(_, _), Some(sample)) => {
+						self.metrics.indirect_probe_rtt.observe(sample);
+					}
+					_ => {}
+				}
+
+				self.update_health(HealthEvent::ProbeSucceeded);
+			}
+			Ping::Request(_, _) => {}
+		}
+
+		Some(ping)
+	}
+
+	/// Handles a `nack` for an indirect probe.
+	pub(crate) fn nack(&mut self, sequence: u64, from: SocketAddr) {
+		self.pings.nack(sequence, from);
+	}
+
+	/// Handles a successful TCP ping of the target of an indirect probe, which has been returned by
+	/// [Protocol::ping_timeout] as [FailResult::DoIndirect]. Returns `false` if the TCP fallback is
+	/// disabled or the indirect probe already finished.
+	pub(crate) fn tcp_ack(&mut self, sequence: u64) -> bool {
+		self.tcp_fallback && self.pings.tcp_ack(sequence)
+	}
+
+	/// Handles an expired ping timer. A failed indirect probe raises the awareness score by the amount
+	/// of missing `nacks`, or by `1` if no other node could be asked to probe the target. A target which
+	/// only answered the TCP ping counts as a successful probe.
+	pub(crate) fn ping_timeout(&mut self, sequence: u64) -> Option<FailResult> {
+		let result = self.pings.fail(sequence)?;
+
+		if !matches!(result, FailResult::SendNack(_)) {
+			self.scheduler.stop_ping_timer(sequence);
+		}
+
+		match &result {
+			FailResult::DoIndirect(target) => {
+				self.scheduler.start_ping_timer(target.sequence, None)
+			}
+			FailResult::SendNack(_) => self.scheduler.start_grace_timer(sequence),
+			FailResult::RequestFailed(_) => {}
+			FailResult::NodeFailed(_, nacks) => {
+				let nacks = nacks.len();
+				self.probe_failed(sequence, nacks);
+			}
+			FailResult::UdpFailed(addr) => {
+				self.expected_nacks.remove(&sequence);
+				self.handler.udp_unreachable(addr);
+				self.update_health(HealthEvent::ProbeSucceeded);
+			}
+		}
+
+		Some(result)
+	}
+
+	fn probe_failed(&mut self, sequence: u64, nacks: usize) {
+		let expected_nacks = self.expected_nacks.remove(&sequence).unwrap_or(0);
+
+		self.update_health(HealthEvent::ProbeFailed {
+			expected_nacks,
+			nacks,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	use tokio::time::Instant;
+
+	use super::*;
+	use crate::clock::ManualClock;
+	use crate::protocol::tests::{addr, protocol, protocol_with};
+	use crate::AdaptiveTimeoutConfig;
+
+	#[tokio::test]
+	async fn probe_outcomes_change_the_awareness() {
+		let mut p = protocol();
+
+		let target = p.probe(addr(1)).unwrap();
+		assert_eq!(
+			p.scheduler.ping_timeout(target.sequence),
+			Some(Duration::from_millis(100))
+		);
+
+		let indirect = match p.ping_timeout(target.sequence) {
+			Some(FailResult::DoIndirect(indirect)) => indirect,
+			result => panic!("unexpected result {:?}", result),
+		};
+		p.indirect_probe(&indirect, &[addr(2), addr(3), addr(4)]);
+		p.nack(indirect.sequence, addr(2));
+
+		// two of three nacks are missing.
+		assert!(matches!(
+			p.ping_timeout(indirect.sequence),
+			Some(FailResult::NodeFailed(_, _))
+		));
+		assert_eq!(p.handler.awareness, vec![3]);
+
+		// the ping timeouts are scaled by the awareness score.
+		let target = p.probe(addr(2)).unwrap();
+		assert_eq!(
+			p.scheduler.ping_timeout(target.sequence),
+			Some(Duration::from_millis(300))
+		);
+
+		assert!(p.ack(target.sequence, None).is_some());
+		assert!(p.ack(target.sequence, None).is_none());
+		assert_eq!(p.scheduler.ping_timeout(target.sequence), None);
+		assert_eq!(p.handler.acks, vec![addr(2)]);
+		assert_eq!(p.handler.awareness, vec![3, 2]);
+
+		// no other node could be asked, so the failed probe counts once.
+		let target = p.probe(addr(3)).unwrap();
+		let indirect = match p.ping_timeout(target.sequence) {
+			Some(FailResult::DoIndirect(indirect)) => indirect,
+			result => panic!("unexpected result {:?}", result),
+		};
+		p.indirect_probe(&indirect, &[]);
+		p.ping_timeout(indirect.sequence);
+		assert_eq!(p.handler.awareness, vec![3, 2, 3]);
+	}
+
+	#[tokio::test]
+	async fn tcp_acks_count_as_successful_probes() {
+		let mut p = protocol().with_tcp_fallback(true);
+		assert!(p.tcp_fallback());
+
+		let indirect = |p: &mut Protocol<_, _>, port| {
+			let target = p.probe(addr(port)).unwrap();
+			let indirect = match p.ping_timeout(target.sequence) {
+				Some(FailResult::DoIndirect(indirect)) => indirect,
+				result => panic!("unexpected result {:?}", result),
+			};
+			p.indirect_probe(&indirect, &[addr(2), addr(3), addr(4)]);
+			indirect
+		};
+
+		let target = indirect(&mut p, 1);
+		p.ping_timeout(target.sequence);
+		assert_eq!(p.handler.awareness, vec![4]);
+
+		let target = indirect(&mut p, 1);
+		assert!(p.tcp_ack(target.sequence));
+		assert!(matches!(
+			p.ping_timeout(target.sequence),
+			Some(FailResult::UdpFailed(_))
+		));
+		assert!(!p.tcp_ack(target.sequence));
+		assert!(p.expected_nacks.is_empty());
+		assert_eq!(p.handler.udp_unreachable, vec![addr(1)]);
+		assert_eq!(p.handler.awareness, vec![4, 3]);
+	}
+
+	#[tokio::test]
+	async fn tcp_acks_are_ignored_without_the_fallback() {
+		let mut p = protocol();
+		assert!(!p.tcp_fallback());
+
+		let target = p.probe(addr(1)).unwrap();
+		let indirect = match p.ping_timeout(target.sequence) {
+			Some(FailResult::DoIndirect(indirect)) => indirect,
+			result => panic!("unexpected result {:?}", result),
+		};
+
+		assert!(!p.tcp_ack(indirect.sequence));
+		assert!(matches!(
+			p.ping_timeout(indirect.sequence),
+			Some(FailResult::NodeFailed(_, _))
+		));
+		assert!(p.handler.udp_unreachable.is_empty());
+	}
+
+	#[tokio::test]
+	async fn acks_record_the_rtt() {
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = protocol_with(None, clock.clone());
+
+		let target = p.probe(addr(1)).unwrap();
+		clock.advance(Duration::from_millis(20));
+		p.ack(target.sequence, None);
+
+		assert_eq!(
+			p.nodes.get(&addr(1)).unwrap().rtt.map(|rtt| rtt.srtt),
+			Some(Duration::from_millis(20))
+		);
+		assert_eq!(p.handler.rtts, vec![(addr(1), Duration::from_millis(20))]);
+		assert_eq!(p.metrics.probe_rtt.count(), 1);
+
+		let target = p.probe(addr(2)).unwrap();
+		let indirect = match p.ping_timeout(target.sequence) {
+			Some(FailResult::DoIndirect(indirect)) => indirect,
+			result => panic!("unexpected result {:?}", result),
+		};
+		clock.advance(Duration::from_millis(20));
+		p.ack(indirect.sequence, None);
+
+		// indirect samples are recorded separately and do not change the rtt of the target.
+		assert!(p.nodes.get(&addr(2)).unwrap().rtt.is_none());
+		assert_eq!(p.handler.rtts.len(), 1);
+		assert_eq!(p.metrics.probe_rtt.count(), 1);
+		assert_eq!(p.metrics.indirect_probe_rtt.count(), 1);
+		assert_eq!(
+			p.metrics.indirect_probe_rtt.sum(),
+			Duration::from_millis(20)
+		);
+	}
+
+	#[tokio::test]
+	async fn probe_timeouts_use_the_rtt() {
+		let config = AdaptiveTimeoutConfig {
+			min: Duration::from_millis(5),
+		};
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let mut p = protocol_with(Some(config), clock.clone());
+
+		let target = p.probe(addr(1)).unwrap();
+		assert_eq!(
+			p.scheduler.ping_timeout(target.sequence),
+			Some(Duration::from_millis(100))
+		);
+
+		clock.advance(Duration::from_millis(10));
+		p.ack(target.sequence, None);
+
+		// srtt + 4 * rttvar = 10ms + 20ms
+		let target = p.probe(addr(1)).unwrap();
+		assert_eq!(
+			p.scheduler.ping_timeout(target.sequence),
+			Some(Duration::from_millis(30))
+		);
+
+		// the indirect probe includes other nodes and uses the base timeout.
+		let indirect = match p.ping_timeout(target.sequence) {
+			Some(FailResult::DoIndirect(indirect)) => indirect,
+			result => panic!("unexpected result {:?}", result),
+		};
+		assert_eq!(p.scheduler.ping_timeout(target.sequence), None);
+		assert_eq!(
+			p.scheduler.ping_timeout(indirect.sequence),
+			Some(Duration::from_millis(100))
+		);
+	}
+}
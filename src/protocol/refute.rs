@@ -0,0 +1,95 @@
+use std::net::SocketAddr;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::awareness::HealthEvent;
+use crate::EventHandler;
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Handles a suspicion about this node with the given `incarnation`. Returns [Some] new incarnation
+	/// number, which must be announced in an `alive` message, or [None] if the suspicion is outdated.
+	pub(crate) fn suspected(&mut self, suspector: &SocketAddr, incarnation: u64) -> Option<u64> {
+		let result = self.refute(incarnation);
+		if result.is_some() {
+			self.handler.suspected(suspector);
+		}
+		result
+	}
+
+	/// Handles a `dead` message about this node with the given `incarnation`. Returns [Some] new
+	/// incarnation number, which must be announced in an `alive` message, or [None] if the message is
+	/// outdated.
+	pub(crate) fn declared_dead(
+		&mut self,
+		declared_by: &SocketAddr,
+		incarnation: u64,
+	) -> Option<u64> {
+		let result = self.refute(incarnation);
+		if result.is_some() {
+			self.handler.declared_dead(declared_by);
+		}
+		result
+	}
+
+	/// Raises the incarnation number above `incarnation`. Having to refute raises the awareness score.
+	fn refute(&mut self, incarnation: u64) -> Option<u64> {
+		if incarnation < self.incarnation {
+			return None;
+		}
+
+		self.incarnation = incarnation.saturating_add(1);
+		self.metrics.refutations.inc();
+		self.update_health(HealthEvent::Refuted);
+		self.store_snapshot();
+
+		Some(self.incarnation)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::protocol::tests::{addr, protocol};
+	use crate::snapshot::Snapshot;
+
+	#[tokio::test]
+	async fn refutations_change_the_awareness() {
+		let mut p = protocol();
+
+		assert_eq!(p.suspected(&addr(1), 0), Some(1));
+		assert_eq!(p.suspected(&addr(2), 0), None);
+		assert_eq!(p.declared_dead(&addr(2), 3), Some(4));
+
+		assert_eq!(p.incarnation(), 4);
+		assert_eq!(p.handler.suspected, vec![addr(1)]);
+		assert_eq!(p.handler.awareness, vec![2, 3]);
+		assert_eq!(p.metrics.refutations.get(), 2);
+	}
+
+	#[tokio::test]
+	async fn refutations_are_stored() {
+		let dir = std::env::temp_dir().join(format!("swimmers-refutations-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+
+		let mut p = protocol().with_state_dir(Some(dir.clone()));
+		p.suspected(&addr(1), 0);
+
+		let mut snapshot = Snapshot::load(&dir).unwrap().unwrap();
+		snapshot.peers.sort();
+		assert_eq!(snapshot.incarnation, 1);
+		assert_eq!(snapshot.peers, vec![addr(1), addr(2), addr(3), addr(4)]);
+		assert_eq!(p.handler.snapshot_failures, 0);
+
+		// the state directory is a file now.
+		std::fs::remove_dir_all(&dir).unwrap();
+		std::fs::write(&dir, "").unwrap();
+		p.suspected(&addr(1), 1);
+		assert_eq!(p.handler.snapshot_failures, 1);
+
+		std::fs::remove_file(dir).unwrap();
+	}
+}
@@ -0,0 +1,136 @@
+use std::io;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::EventHandler;
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Raises the incarnation number above the one of the [Snapshot] in the state directory.
+	/// Returns the configured `seeds` extended by the stored peers.
+	pub(super) fn restore_snapshot(&mut self, seeds: &[String]) -> Box<[String]> {
+		let dir = match self.state_dir.as_ref() {
+			Some(dir) => dir,
+			None => return seeds.into(),
+		};
+
+		match Snapshot::load(dir) {
+			Ok(Some(snapshot)) => {
+				self.incarnation = snapshot.restart_incarnation(self.incarnation);
+				snapshot.seeds(seeds)
+			}
+			Ok(None) => seeds.into(),
+			Err(e) => {
+				self.handler.snapshot_failed(match e {
+					SnapshotError::Io(e) => e,
+					e => io::Error::new(io::ErrorKind::InvalidData, e),
+				});
+				seeds.into()
+			}
+		}
+	}
+
+	/// Stores the incarnation number and the known peers if a state directory is set and they
+	/// changed since the last [Snapshot].
+	pub(crate) fn store_snapshot(&mut self) {
+		let dir = match self.state_dir.as_ref() {
+			Some(dir) => dir,
+			None => return,
+		};
+
+		let addr = self.addr;
+		let peers = self.nodes.get_map().values().filter(|n| n.addr != addr);
+		let snapshot = Snapshot::new(self.incarnation, peers);
+		if self.stored.as_ref() == Some(&snapshot) {
+			return;
+		}
+
+		match snapshot.store(dir) {
+			Ok(()) => self.stored = Some(snapshot),
+			Err(e) => self.handler.snapshot_failed(e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::protocol::tests::{addr, protocol};
+	use crate::{Node, NodeState};
+
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("swimmers-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		dir
+	}
+
+	#[tokio::test]
+	async fn snapshots_are_restored_at_startup() {
+		let dir = temp_dir("restore");
+		Snapshot {
+			incarnation: 5,
+			peers: vec![addr(7)],
+		}
+		.store(&dir)
+		.unwrap();
+
+		let mut p = protocol().with_state_dir(Some(dir.clone()));
+		let seeds = p.start(&["seed.local:7946".to_string()]);
+
+		assert_eq!(p.incarnation(), 6);
+		assert_eq!(
+			&*seeds,
+			&["seed.local:7946".to_string(), addr(7).to_string()]
+		);
+
+		let snapshot = Snapshot::load(&dir).unwrap().unwrap();
+		assert_eq!(snapshot.incarnation, 6);
+		assert_eq!(snapshot.peers, vec![addr(1), addr(2), addr(3), addr(4)]);
+
+		fs::write(dir.join("snapshot"), "nope").unwrap();
+		let mut p = protocol().with_state_dir(Some(dir.clone()));
+		assert!(p.start(&[]).is_empty());
+		assert_eq!(p.incarnation(), 0);
+		assert_eq!(p.handler.snapshot_failures, 1);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn membership_changes_are_stored() {
+		let dir = temp_dir("membership");
+
+		let mut p = protocol().with_state_dir(Some(dir.clone()));
+		p.start(&[]);
+
+		p.update_node(Node {
+			addr: addr(2),
+			state: NodeState::Dead(1),
+			metadata: None,
+			rtt: None,
+		});
+		p.remove_node(&addr(3));
+
+		let snapshot = Snapshot::load(&dir).unwrap().unwrap();
+		assert_eq!(snapshot.peers, vec![addr(1), addr(4)]);
+
+		// unchanged snapshots are not written again.
+		fs::remove_dir_all(&dir).unwrap();
+		p.update_node(Node {
+			addr: addr(1),
+			state: NodeState::Alive(2),
+			metadata: None,
+			rtt: None,
+		});
+		assert_eq!(Snapshot::load(&dir).unwrap(), None);
+		assert_eq!(p.handler.snapshot_failures, 0);
+	}
+}
@@ -0,0 +1,169 @@
+use std::net::SocketAddr;
+
+use rand::Rng;
+
+use super::Protocol;
+use crate::scheduler::KillRequest;
+use crate::suspicions::SuspicionResult;
+use crate::{Cause, EventHandler, NodeState, SuspicionInfo};
+
+impl<E, R> Protocol<E, R>
+where
+	E: EventHandler,
+	R: Rng,
+{
+	/// Handles a suspicion of `suspector` about the node `addr` with the given `incarnation`.
+	/// A new suspicion starts the suspicion timer, each further suspector shrinks its timeout.
+	///
+	/// Returns `true` if the suspicion is new or confirms an active suspicion and must be gossiped
+	/// further.
+	pub(crate) fn suspect(
+		&mut self,
+		addr: SocketAddr,
+		incarnation: u64,
+		suspector: SocketAddr,
+	) -> bool {
+		let state = NodeState::Suspect(incarnation);
+		match self.nodes.get(&addr) {
+			Some(node) if node.state <= state => {}
+			_ => return false,
+		}
+
+		match self.suspicions.suspect(addr, incarnation, suspector) {
+			Some(SuspicionResult::New) | Some(SuspicionResult::Reset) => {
+				self.scheduler
+					.start_suspicion(KillRequest { addr, incarnation });
+
+				if let Some(mut node) = self.nodes.get_mut(&addr) {
+					if node.state < state {
+						node.set_state(state);
+						self.handler.node(&node, Cause::Suspicion);
+					}
+				}
+				self.membership_changed();
+
+				true
+			}
+			Some(SuspicionResult::Update(suspectors)) => {
+				self.scheduler.update_suspectors(&addr, suspectors);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Handles an expired suspicion timer. Declares the node dead unless it refuted the suspicion in
+	/// the meantime.
+	///
+	/// Returns `true` if the node has been declared dead, which must be announced in a `dead` message.
+	pub(crate) fn suspicion_timeout(&mut self, kill_req: KillRequest) -> bool {
+		match self.suspicions.get(&kill_req.addr) {
+			Some(suspicion) if suspicion.incarnation == kill_req.incarnation => {}
+			_ => return false,
+		}
+		self.end_suspicion(&kill_req.addr);
+
+		match self.nodes.get_mut(&kill_req.addr) {
+			Some(mut node) if node.state == NodeState::Suspect(kill_req.incarnation) => {
+				node.set_state(NodeState::Dead(kill_req.incarnation));
+				self.handler.node(&node, Cause::Death);
+				self.membership_changed();
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Returns the details of every active suspicion about other nodes.
+	pub(crate) fn suspicions(&self) -> Vec<SuspicionInfo> {
+		let scheduler = &self.scheduler;
+		self.suspicions
+			.suspicions(|addr| scheduler.suspicion_timeout(addr))
+	}
+
+	/// Removes the suspicion about `addr` and stops its timer.
+	pub(super) fn end_suspicion(&mut self, addr: &SocketAddr) {
+		if self.suspicions.remove(addr).is_some() {
+			self.scheduler.stop_suspicion(addr);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::protocol::tests::{addr, protocol};
+	use crate::Node;
+
+	#[tokio::test]
+	async fn suspicions_are_tracked() {
+		let mut p = protocol();
+
+		assert!(p.suspect(addr(1), 1, addr(2)));
+		assert!(!p.suspect(addr(1), 1, addr(2)));
+		assert!(!p.suspect(addr(1), 0, addr(3)));
+		assert!(!p.suspect(addr(9), 1, addr(2)));
+		assert_eq!(p.nodes.get(&addr(1)).unwrap().state, NodeState::Suspect(1));
+		assert_eq!(
+			p.handler.nodes,
+			vec![(addr(1), NodeState::Suspect(1), "suspicion")]
+		);
+
+		let suspicions = p.suspicions();
+		assert_eq!(suspicions.len(), 1);
+		let first = suspicions[0].timeout.unwrap();
+
+		// the confirmation shrinks the timeout.
+		assert!(p.suspect(addr(1), 1, addr(3)));
+		let suspicions = p.suspicions();
+		assert_eq!(suspicions[0].suspectors.len(), 2);
+		assert!(suspicions[0].timeout.unwrap() < first);
+		assert_eq!(p.metrics.suspicions_confirmed.get(), 1);
+	}
+
+	#[tokio::test]
+	async fn refutations_end_suspicions() {
+		let mut p = protocol();
+
+		assert!(p.suspect(addr(1), 1, addr(2)));
+		assert!(p.update_node(Node {
+			addr: addr(1),
+			state: NodeState::Alive(2),
+			metadata: None,
+			rtt: None,
+		}));
+
+		assert!(p.suspicions().is_empty());
+		assert_eq!(p.scheduler.suspicion_timeout(&addr(1)), None);
+		assert!(!p.suspicion_timeout(KillRequest {
+			addr: addr(1),
+			incarnation: 1,
+		}));
+		assert_eq!(p.nodes.get(&addr(1)).unwrap().state, NodeState::Alive(2));
+	}
+
+	#[tokio::test]
+	async fn expired_suspicions_kill_the_node() {
+		let mut p = protocol();
+
+		assert!(p.suspect(addr(1), 1, addr(2)));
+		assert!(!p.suspicion_timeout(KillRequest {
+			addr: addr(1),
+			incarnation: 0,
+		}));
+		assert!(p.suspicion_timeout(KillRequest {
+			addr: addr(1),
+			incarnation: 1,
+		}));
+
+		assert!(p.suspicions().is_empty());
+		assert_eq!(p.nodes.get(&addr(1)).unwrap().state, NodeState::Dead(1));
+		assert_eq!(
+			p.handler.nodes.last(),
+			Some(&(addr(1), NodeState::Dead(1), "death"))
+		);
+
+		// a dead node cannot be suspected with the same incarnation.
+		assert!(!p.suspect(addr(1), 1, addr(3)));
+	}
+}
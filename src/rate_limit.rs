@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::clock::Clock;
+use crate::metrics::{MessageType, Metrics};
+use crate::{RateLimit, RateLimitConfig};
+
+/// Prune idle buckets once this many buckets exist.
+const PRUNE_THRESHOLD: usize = 4096;
+/// The min time between two prunes, so a large map is not scanned for each message.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RateLimitError {
+	#[error("invalid rate `{1}` for {0:?} messages, the rate must be positive")]
+	InvalidRate(MessageType, f64),
+}
+
+/// A token bucket which refills at a constant rate up to its capacity.
+#[derive(Debug)]
+struct TokenBucket {
+	tokens: f64,
+	last: Instant,
+}
+
+impl TokenBucket {
+	fn new(limit: &RateLimit, now: Instant) -> Self {
+		Self {
+			tokens: limit.burst.get().into(),
+			last: now,
+		}
+	}
+
+	fn refill(&mut self, limit: &RateLimit, now: Instant) {
+		let passed = (now - self.last).as_secs_f64();
+		let burst: f64 = limit.burst.get().into();
+
+		self.tokens = f64::min(burst, self.tokens + passed * limit.rate);
+		self.last = now;
+	}
+
+	fn try_take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+		self.refill(limit, now);
+
+		if self.tokens >= 1.0 {
+			self.tokens -= 1.0;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+/// Limits the rate of incoming messages per source address and [MessageType].
+///
+/// Each source address gets a token bucket for each limited [MessageType]. Messages are dropped and
+/// counted in [Metrics::dropped] once the bucket is empty. Ports are ignored, so a source cannot
+/// bypass the limit by sending from multiple ports.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+	config: RateLimitConfig,
+	buckets: HashMap<(IpAddr, MessageType), TokenBucket>,
+	/// The time of the last prune.
+	last_prune: Option<Instant>,
+	clock: Arc<dyn Clock>,
+	metrics: Arc<Metrics>,
+}
+
+impl RateLimiter {
+	/// Fails if a rate of the config is not positive.
+	pub(crate) fn new(
+		config: RateLimitConfig,
+		clock: Arc<dyn Clock>,
+		metrics: Arc<Metrics>,
+	) -> Result<Self, RateLimitError> {
+		for kind in MessageType::ALL.iter().copied() {
+			if let Some(limit) = config.limit(kind) {
+				if limit.rate.is_nan() || limit.rate <= 0.0 {
+					return Err(RateLimitError::InvalidRate(kind, limit.rate));
+				}
+			}
+		}
+
+		Ok(Self {
+			config,
+			buckets: HashMap::new(),
+			last_prune: None,
+			clock,
+			metrics,
+		})
+	}
+
+	/// Returns `true` if a message of the given [MessageType] from `from` may be processed.
+	pub(crate) fn check(&mut self, from: SocketAddr, kind: MessageType) -> bool {
+		let now = self.clock.now();
+		self.check_at(from, kind, now)
+	}
+
+	fn check_at(&mut self, from: SocketAddr, kind: MessageType, now: Instant) -> bool {
+		let limit = match self.config.limit(kind) {
+			Some(limit) => *limit,
+			None => return true,
+		};
+
+		let prune_due = match self.last_prune {
+			Some(last) => now.saturating_duration_since(last) >= PRUNE_INTERVAL,
+			None => true,
+		};
+
+		if prune_due && self.buckets.len() >= PRUNE_THRESHOLD {
+			self.prune(now);
+		}
+
+		let allowed = self
+			.buckets
+			.entry((from.ip(), kind))
+			.or_insert_with(|| TokenBucket::new(&limit, now))
+			.try_take(&limit, now);
+
+		if !allowed {
+			self.metrics.dropped.inc(kind);
+		}
+
+		allowed
+	}
+
+	/// Removes all buckets which are full again, since they behave like new buckets.
+	fn prune(&mut self, now: Instant) {
+		self.last_prune = Some(now);
+		let config = &self.config;
+
+		self.buckets
+			.retain(|(_, kind), bucket| match config.limit(*kind) {
+				Some(limit) => {
+					bucket.refill(limit, now);
+					bucket.tokens < limit.burst.get().into()
+				}
+				None => false,
+			});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::num::NonZeroU32;
+	use std::time::Duration;
+
+	use super::*;
+	use crate::clock::TokioClock;
+
+	fn addr(s: &str) -> SocketAddr {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn limits_per_source_and_type() {
+		let config = RateLimitConfig {
+			ping: Some(RateLimit {
+				rate: 10.0,
+				burst: NonZeroU32::new(2).unwrap(),
+			}),
+			..Default::default()
+		};
+		let metrics = Arc::new(Metrics::default());
+		let mut r = RateLimiter::new(config, Arc::new(TokioClock), metrics.clone()).unwrap();
+
+		let now = Instant::now();
+		let a = addr("10.0.0.1:1");
+
+		assert!(r.check_at(a, MessageType::Ping, now));
+		assert!(r.check_at(addr("10.0.0.1:2"), MessageType::Ping, now));
+		assert!(!r.check_at(a, MessageType::Ping, now));
+		assert!(r.check_at(addr("10.0.0.2:1"), MessageType::Ping, now));
+		assert!(r.check_at(a, MessageType::Suspect, now));
+
+		let later = now + Duration::from_millis(100);
+		assert!(r.check_at(a, MessageType::Ping, later));
+		assert!(!r.check_at(a, MessageType::Ping, later));
+
+		assert_eq!(metrics.dropped.get(MessageType::Ping).get(), 2);
+
+		r.prune(now + Duration::from_secs(1));
+		assert!(r.buckets.is_empty());
+	}
+
+	#[test]
+	fn prunes_at_most_once_per_interval() {
+		let config = RateLimitConfig {
+			ping: Some(RateLimit {
+				rate: 0.1,
+				burst: NonZeroU32::new(1).unwrap(),
+			}),
+			..Default::default()
+		};
+		let mut r =
+			RateLimiter::new(config, Arc::new(TokioClock), Arc::new(Metrics::default())).unwrap();
+
+		let now = Instant::now();
+		let flood =
+			|i: usize| SocketAddr::from(([10, (i >> 16) as u8, (i >> 8) as u8, i as u8], 1));
+
+		for i in 0..PRUNE_THRESHOLD {
+			r.check_at(flood(i), MessageType::Ping, now);
+		}
+		assert!(r.last_prune.is_none());
+
+		// the buckets stay empty, so the map stays above the threshold.
+		r.check_at(flood(PRUNE_THRESHOLD), MessageType::Ping, now);
+		assert_eq!(r.last_prune, Some(now));
+
+		for i in 1..100 {
+			let at = now + Duration::from_millis(i * 10);
+			r.check_at(flood(PRUNE_THRESHOLD + i as usize), MessageType::Ping, at);
+			assert_eq!(r.last_prune, Some(now));
+		}
+
+		let later = now + PRUNE_INTERVAL;
+		r.check_at(flood(0), MessageType::Ping, later);
+		assert_eq!(r.last_prune, Some(later));
+	}
+
+	#[test]
+	fn rejects_invalid_rates() {
+		for rate in [0.0, -1.0, f64::NAN].iter().copied() {
+			let config = RateLimitConfig {
+				suspect: Some(RateLimit {
+					rate,
+					burst: NonZeroU32::new(1).unwrap(),
+				}),
+				..Default::default()
+			};
+
+			let result =
+				RateLimiter::new(config, Arc::new(TokioClock), Arc::new(Metrics::default()));
+			assert!(matches!(
+				result,
+				Err(RateLimitError::InvalidRate(MessageType::Suspect, _))
+			));
+		}
+	}
+}
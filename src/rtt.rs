@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// A smoothed round-trip time estimate of a [Node](crate::Node).
+///
+/// The estimate is calculated from `ping`/`ack` pairs as specified in [RFC 6298](https://tools.ietf.org/html/rfc6298).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rtt {
+	/// The smoothed round-trip time.
+	pub srtt: Duration,
+	/// The round-trip time variance.
+	pub rttvar: Duration,
+	/// The most recent sample.
+	pub last: Duration,
+}
+
+impl Rtt {
+	const ALPHA: f64 = 1.0 / 8.0;
+	const BETA: f64 = 1.0 / 4.0;
+	const K: u32 = 4;
+
+	/// Creates a new estimate from the first sample.
+	pub(crate) fn new(sample: Duration) -> Self {
+		Self {
+			srtt: sample,
+			rttvar: sample / 2,
+			last: sample,
+		}
+	}
+
+	/// Updates the estimate with a new sample.
+	pub(crate) fn update(&mut self, sample: Duration) {
+		let diff = self.srtt.abs_diff(sample);
+
+		self.rttvar = self.rttvar.mul_f64(1.0 - Self::BETA) + diff.mul_f64(Self::BETA);
+		self.srtt = self.srtt.mul_f64(1.0 - Self::ALPHA) + sample.mul_f64(Self::ALPHA);
+		self.last = sample;
+	}
+
+	/// Returns the retransmission timeout `srtt + 4 * rttvar` without a clock granularity or bounds.
+	pub fn rto(&self) -> Duration {
+		self.srtt + Self::K * self.rttvar
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn update() {
+		let mut rtt = Rtt::new(Duration::from_millis(100));
+		assert_eq!(rtt.srtt, Duration::from_millis(100));
+		assert_eq!(rtt.rttvar, Duration::from_millis(50));
+
+		rtt.update(Duration::from_millis(180));
+		assert_eq!(rtt.srtt, Duration::from_millis(110));
+		assert_eq!(
+			rtt.rttvar,
+			Duration::from_millis(57) + Duration::from_micros(500)
+		);
+		assert_eq!(rtt.last, Duration::from_millis(180));
+		assert_eq!(rtt.rto(), Duration::from_millis(340));
+
+		for _ in 0..100 {
+			rtt.update(Duration::from_millis(20));
+		}
+		assert!(rtt.srtt - Duration::from_millis(20) < Duration::from_micros(10));
+		assert!(rtt.rttvar < Duration::from_micros(10));
+	}
+}
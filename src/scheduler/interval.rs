@@ -1,45 +1,121 @@
 use std::num::NonZeroU32;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 use std::sync::Arc;
 
 use crossbeam_utils::atomic::AtomicCell;
-use tokio::sync::Notify;
-use tokio::time::{sleep, Instant};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::Instant;
 
+use crate::clock::Clock;
 use crate::handle::Handle;
+use crate::JitterConfig;
 
-#[derive(Debug, Clone)]
+/// Receives the ticks of an [Interval]. At most one tick is buffered, further ticks are dropped
+/// until the buffered one has been received.
+#[derive(Debug)]
 pub(crate) struct IntervalNotifier {
-	notifier: Arc<Notify>,
+	rx: Receiver<()>,
 }
 
 impl IntervalNotifier {
-	pub(crate) async fn next(&self) {
-		self.notifier.notified().await;
+	pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<()>> {
+		self.rx.poll_recv(cx)
 	}
 }
 
-struct Interval {
+/// Randomizes the durations between the ticks of an [Interval].
+#[derive(Debug)]
+pub(super) struct Jitter {
+	factor: f64,
+	spread_first_tick: bool,
+	rng: SmallRng,
+}
+
+impl Jitter {
+	pub(super) fn new(config: JitterConfig, rng: SmallRng) -> Self {
+		let factor = if config.factor.is_nan() {
+			0.0
+		} else {
+			config.factor.clamp(0.0, 1.0)
+		};
+
+		Self {
+			factor,
+			spread_first_tick: config.spread_first_tick,
+			rng,
+		}
+	}
+
+	/// Returns a new [Jitter] with the same config, which is seeded by this one.
+	fn fork(&mut self) -> Self {
+		Self {
+			factor: self.factor,
+			spread_first_tick: self.spread_first_tick,
+			rng: SmallRng::seed_from_u64(self.rng.gen()),
+		}
+	}
+
+	fn first_tick(&mut self, d: Duration) -> Duration {
+		if self.spread_first_tick {
+			d.mul_f64(self.rng.gen_range(0.0..=1.0))
+		} else {
+			self.tick(d)
+		}
+	}
+
+	fn tick(&mut self, d: Duration) -> Duration {
+		if self.factor > 0.0 {
+			d.mul_f64(self.rng.gen_range(1.0 - self.factor..=1.0 + self.factor))
+		} else {
+			d
+		}
+	}
+}
+
+pub(super) struct Interval {
 	last_started: Arc<AtomicCell<Instant>>,
-	notifier: Arc<Notify>,
+	tx: Sender<()>,
+	clock: Arc<dyn Clock>,
+	jitter: Option<Jitter>,
 	handle: Handle,
 }
 
 impl Interval {
-	fn new(d: Duration) -> (IntervalNotifier, Self) {
-		let last_started = Arc::new(AtomicCell::new(Instant::now()));
-		let notifier = Arc::new(Notify::new());
+	pub(super) fn new(
+		d: Duration,
+		clock: Arc<dyn Clock>,
+		mut jitter: Option<Jitter>,
+	) -> (IntervalNotifier, Self) {
+		let last_started = Arc::new(AtomicCell::new(clock.now()));
+		let (tx, rx) = channel(1);
+
+		let first = match jitter.as_mut() {
+			Some(jitter) => jitter.first_tick(d),
+			None => d,
+		};
 
-		let handle = tokio::spawn(task(d, last_started.clone(), notifier.clone()));
+		let handle = tokio::spawn(task(
+			d,
+			first,
+			last_started.clone(),
+			tx.clone(),
+			clock.clone(),
+			jitter.as_mut().map(Jitter::fork),
+		));
 
 		let interval = Self {
 			last_started,
-			notifier: notifier.clone(),
+			tx,
+			clock,
+			jitter,
 			handle: Handle::from(handle),
 		};
 
-		let notifier = IntervalNotifier { notifier };
+		let notifier = IntervalNotifier { rx };
 
 		(notifier, interval)
 	}
@@ -47,27 +123,45 @@ impl Interval {
 	fn reset(&mut self, d: Duration) {
 		self.handle.abort();
 
-		let handle = tokio::spawn(task(d, self.last_started.clone(), self.notifier.clone()));
+		let mut jitter = self.jitter.as_mut().map(Jitter::fork);
+		let first = match jitter.as_mut() {
+			Some(jitter) => jitter.tick(d),
+			None => d,
+		};
+
+		let handle = tokio::spawn(task(
+			d,
+			first,
+			self.last_started.clone(),
+			self.tx.clone(),
+			self.clock.clone(),
+			jitter,
+		));
 
 		self.handle = Handle::from(handle);
 	}
 }
 
-async fn task(d: Duration, last_started: Arc<AtomicCell<Instant>>, notifier: Arc<Notify>) {
+/// Sends a tick `next` after the last tick and then every `d`, randomized by the [Jitter] if set.
+async fn task(
+	d: Duration,
+	mut next: Duration,
+	last_started: Arc<AtomicCell<Instant>>,
+	tx: Sender<()>,
+	clock: Arc<dyn Clock>,
+	mut jitter: Option<Jitter>,
+) {
 	loop {
-		let now = Instant::now();
-		let passed = now - last_started.load();
+		clock.sleep_until(last_started.load() + next).await;
+		// drop the tick if the last one has not been received yet.
+		let _ = tx.try_send(());
 
-		// TODO: replace with `Duration::saturating_sub` once stable
-		let d = match d.checked_sub(passed) {
-			Some(d) => d,
-			None => Duration::from_nanos(0),
-		};
-
-		sleep(d).await;
-		notifier.notify_waiters();
+		last_started.store(clock.now());
 
-		last_started.store(Instant::now());
+		next = match jitter.as_mut() {
+			Some(jitter) => jitter.tick(d),
+			None => d,
+		};
 	}
 }
 
@@ -77,61 +171,167 @@ async fn task(d: Duration, last_started: Arc<AtomicCell<Instant>>, notifier: Arc
 /// [AwarenessInterval] is used  for the `ping` and `gossip` intervals.
 pub(super) struct AwarenessInterval {
 	base_interval: Duration,
+	awareness: NonZeroU32,
 	inner: Interval,
 }
 
 impl AwarenessInterval {
-	pub(super) fn new(base_interval: Duration) -> (IntervalNotifier, Self) {
-		let (notifier, inner) = Interval::new(base_interval);
+	pub(super) fn new(
+		base_interval: Duration,
+		clock: Arc<dyn Clock>,
+		jitter: Jitter,
+	) -> (IntervalNotifier, Self) {
+		let (notifier, inner) = Interval::new(base_interval, clock, Some(jitter));
 		let this = Self {
 			base_interval,
+			awareness: NonZeroU32::new(1).unwrap(),
 			inner,
 		};
 		(notifier, this)
 	}
 
 	pub(super) fn update(&mut self, awareness: NonZeroU32) -> Duration {
+		self.awareness = awareness;
+
 		let d = awareness.get() * self.base_interval;
 		self.inner.reset(d);
 		d
 	}
+
+	/// Replaces the base interval and resets the interval using the last awareness score.
+	pub(super) fn set_base_interval(&mut self, base_interval: Duration) -> Duration {
+		self.base_interval = base_interval;
+		self.update(self.awareness)
+	}
 }
 
 pub(super) struct SyncInterval {
 	base_interval: Duration,
 	scale: u32,
+	node_count: NonZeroU32,
 	inner: Interval,
 }
 
 impl SyncInterval {
-	pub(super) fn new(base_interval: Duration, scale: NonZeroU32) -> (IntervalNotifier, Self) {
-		let (notifier, inner) = Interval::new(base_interval);
+	/// Returns a new [SyncInterval] scaled for a cluster of `node_count` nodes.
+	pub(super) fn new(
+		base_interval: Duration,
+		scale: NonZeroU32,
+		node_count: NonZeroU32,
+		clock: Arc<dyn Clock>,
+		jitter: Jitter,
+	) -> (IntervalNotifier, Self) {
+		let d = Self::scaled(base_interval, scale.get(), node_count.get());
+		let (notifier, inner) = Interval::new(d, clock, Some(jitter));
 		let this = Self {
 			base_interval,
 			scale: scale.into(),
+			node_count,
 			inner,
 		};
 		(notifier, this)
 	}
 
+	/// Replaces the base interval and resets the interval using the last node count.
+	pub(super) fn set_base_interval(&mut self, base_interval: Duration) -> Duration {
+		self.base_interval = base_interval;
+		self.update(self.node_count)
+	}
+
 	pub(super) fn update(&mut self, node_count: NonZeroU32) -> Duration {
-		let node_count = node_count.get();
+		self.node_count = node_count;
 
-		let d = if node_count > self.scale {
+		let d = Self::scaled(self.base_interval, self.scale, node_count.get());
+		self.inner.reset(d);
+		d
+	}
+
+	fn scaled(base_interval: Duration, scale: u32, node_count: u32) -> Duration {
+		if node_count > scale {
 			let node_count: f64 = node_count.into();
 			let node_count = node_count.log2();
 
-			let scale: f64 = self.scale.into();
+			let scale: f64 = scale.into();
 			let scale = scale.log2();
 
 			let multiplier = f64::ceil(node_count - scale) + 1.0;
 
-			self.base_interval.mul_f64(multiplier)
+			base_interval.mul_f64(multiplier)
 		} else {
-			self.base_interval
+			base_interval
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::clock::ManualClock;
+
+	fn jitter(seed: u64) -> Jitter {
+		let config = JitterConfig {
+			factor: 0.5,
+			spread_first_tick: true,
 		};
+		Jitter::new(config, SmallRng::seed_from_u64(seed))
+	}
 
-		self.inner.reset(d);
-		d
+	/// Advances the clock in steps of 10ms and returns the time of each tick relative to the start.
+	async fn ticks(seed: u64, d: Duration, steps: u32) -> Vec<Duration> {
+		let start = Instant::now();
+		let manual = Arc::new(ManualClock::new(start));
+		let (mut notifier, _interval) = Interval::new(d, manual.clone(), Some(jitter(seed)));
+
+		let mut result = Vec::new();
+		for _ in 0..steps {
+			manual.advance(Duration::from_millis(10));
+			for _ in 0..3 {
+				tokio::task::yield_now().await;
+			}
+
+			if notifier.rx.try_recv().is_ok() {
+				result.push(manual.now() - start);
+			}
+		}
+
+		result
+	}
+
+	#[test]
+	fn jitter_stays_within_factor() {
+		let d = Duration::from_secs(1);
+		let mut jitter = jitter(1);
+
+		assert!(jitter.first_tick(d) <= d);
+
+		let ticks: Vec<_> = (0..1000).map(|_| jitter.tick(d)).collect();
+		assert!(ticks.iter().all(|t| *t >= d / 2 && *t <= d * 3 / 2));
+		assert!(ticks.iter().any(|t| *t < d * 9 / 10));
+		assert!(ticks.iter().any(|t| *t > d * 11 / 10));
+
+		let unclamped = JitterConfig {
+			factor: 3.0,
+			spread_first_tick: false,
+		};
+		let mut jitter = Jitter::new(unclamped, SmallRng::seed_from_u64(1));
+		assert!((0..1000).all(|_| jitter.tick(d) <= d * 2));
+	}
+
+	#[tokio::test]
+	async fn jittered_ticks_are_deterministic() {
+		let d = Duration::from_millis(100);
+
+		let a = ticks(3, d, 200).await;
+		let b = ticks(3, d, 200).await;
+		let c = ticks(4, d, 200).await;
+
+		assert_eq!(a, b);
+		assert_ne!(a, c);
+
+		assert!(a.len() > 10);
+		assert!(a[0] <= d);
+		for gap in a.windows(2).map(|w| w[1] - w[0]) {
+			assert!(gap >= d / 2 && gap <= d * 3 / 2 + Duration::from_millis(10));
+		}
 	}
 }
@@ -1,48 +1,113 @@
 use std::convert::TryInto;
+use std::net::SocketAddr;
 use std::num::{NonZeroU32, NonZeroUsize};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use interval::{AwarenessInterval, SyncInterval};
+use futures_core::{ready, Stream};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use interval::{AwarenessInterval, Interval, Jitter, SyncInterval};
 use ping::PingTimers;
+use rejoin::RejoinTimer;
 use suspicion::{State, SuspicionTimers, TimeoutCalculator};
+use timer::{Timeout, Timers};
 use tokio::sync::mpsc::Receiver;
+use tokio::time::Instant;
 
 mod interval;
 mod ping;
+mod rejoin;
 mod suspicion;
 mod timer;
 
 pub(crate) use interval::IntervalNotifier;
 pub(crate) use suspicion::KillRequest;
 
+use crate::awareness::{Awareness, HealthEvent};
+use crate::clock::{Clock, TokioClock};
 use crate::consts::MAX_NON_ZERO_U32;
-use crate::SchedulerConfig;
+use crate::handle::Handle;
+use crate::metrics::Metrics;
+use crate::node_set::Isolation;
+use crate::rtt::Rtt;
+use crate::{EventHandler, Reconfiguration, SchedulerConfig};
 
 pub(crate) struct SchedulerEvents {
 	sync_notifier: IntervalNotifier,
 	ping_notifier: IntervalNotifier,
 	gossip_notifier: IntervalNotifier,
+	reconnect_notifier: IntervalNotifier,
+
+	timeouts: Receiver<Timeout>,
 
-	suspicion_timeout: Receiver<KillRequest>,
-	ping_timeout: Receiver<u64>,
+	/// The source which is polled first.
+	next_source: usize,
 }
 
 pub(crate) enum SchedulerEvent {
 	SyncInterval,
 	PingInterval,
 	GossipInterval,
+	/// Signals to contact a random dead node.
+	ReconnectInterval,
 	SuspicionTimeout(KillRequest),
 	PingTimeout(u64),
+	/// Signals to rejoin the cluster using the seeds.
+	RejoinTimeout,
 }
 
 impl SchedulerEvents {
-	// TODO: use futures::Stream instead.
-	pub(crate) async fn next(&mut self) -> SchedulerEvent {
-		tokio::select! {
-			_ = self.sync_notifier.next() => SchedulerEvent::SyncInterval,
-			_ = self.ping_notifier.next() => SchedulerEvent::PingInterval,
-			_ = self.gossip_notifier.next() => SchedulerEvent::GossipInterval,
-			Some(k) = self.suspicion_timeout.recv() => SchedulerEvent::SuspicionTimeout(k),
-			Some(i) = self.ping_timeout.recv() => SchedulerEvent::PingTimeout(i),
+	/// The amount of event sources.
+	const SOURCES: usize = 5;
+
+	fn poll_source(&mut self, source: usize, cx: &mut Context<'_>) -> Poll<Option<SchedulerEvent>> {
+		let event = match source {
+			0 => ready!(self.sync_notifier.poll_next(cx)).map(|_| SchedulerEvent::SyncInterval),
+			1 => ready!(self.ping_notifier.poll_next(cx)).map(|_| SchedulerEvent::PingInterval),
+			2 => ready!(self.gossip_notifier.poll_next(cx)).map(|_| SchedulerEvent::GossipInterval),
+			3 => ready!(self.reconnect_notifier.poll_next(cx))
+				.map(|_| SchedulerEvent::ReconnectInterval),
+			_ => ready!(self.timeouts.poll_recv(cx)).map(|t| match t {
+				Timeout::Suspicion(k) => SchedulerEvent::SuspicionTimeout(k),
+				Timeout::Ping(i) => SchedulerEvent::PingTimeout(i),
+				Timeout::Rejoin => SchedulerEvent::RejoinTimeout,
+			}),
+		};
+
+		Poll::Ready(event)
+	}
+}
+
+/// Polls the event sources in turns, starting after the source of the last event, so a busy source
+/// cannot starve the others. Ends once every source has been closed.
+impl Stream for SchedulerEvents {
+	type Item = SchedulerEvent;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		let start = self.next_source;
+		let mut closed = 0;
+
+		for i in 0..Self::SOURCES {
+			let source = (start + i) % Self::SOURCES;
+
+			match self.poll_source(source, cx) {
+				Poll::Ready(Some(event)) => {
+					self.next_source = (source + 1) % Self::SOURCES;
+					return Poll::Ready(Some(event));
+				}
+				Poll::Ready(None) => closed += 1,
+				Poll::Pending => {}
+			}
+		}
+
+		if closed == Self::SOURCES {
+			Poll::Ready(None)
+		} else {
+			Poll::Pending
 		}
 	}
 }
@@ -51,48 +116,105 @@ pub(crate) struct Scheduler {
 	sync_interval: SyncInterval,
 	ping_interval: AwarenessInterval,
 	gossip_interval: AwarenessInterval,
+	reconnect_interval: Interval,
 
 	ping_timers: PingTimers,
 	suspicion_timers: SuspicionTimers,
+	rejoin_timer: RejoinTimer,
+	/// Drives all timers. Aborted once the scheduler is dropped.
+	_timer_driver: Handle,
+
+	clock: Arc<dyn Clock>,
 }
 
 impl Scheduler {
-	fn new(config: SchedulerConfig, node_count: NonZeroUsize) -> (SchedulerEvents, Self) {
-		let (sync_notifier, sync_interval) =
-			SyncInterval::new(config.sync.base_interval, config.sync.scale);
-		let (ping_notifier, ping_interval) = AwarenessInterval::new(config.ping.base_interval);
+	/// Returns a new [Scheduler]. The jitter of the intervals is drawn from `rng`, so a seeded `rng`
+	/// makes the intervals deterministic when driven by a [ManualClock](crate::ManualClock).
+	pub(crate) fn new<R: Rng>(
+		config: SchedulerConfig,
+		node_count: NonZeroUsize,
+		metrics: Arc<Metrics>,
+		rng: &mut R,
+	) -> (SchedulerEvents, Self) {
+		let clock = config.clock.unwrap_or_else(|| Arc::new(TokioClock));
+		let jitter_config = config.jitter;
+		let mut jitter = || Jitter::new(jitter_config, SmallRng::seed_from_u64(rng.gen()));
+
+		let node_count = node_count.try_into().unwrap_or(MAX_NON_ZERO_U32);
+
+		let (sync_notifier, sync_interval) = SyncInterval::new(
+			config.sync.base_interval,
+			config.sync.scale,
+			node_count,
+			clock.clone(),
+			jitter(),
+		);
+		let (ping_notifier, ping_interval) =
+			AwarenessInterval::new(config.ping.base_interval, clock.clone(), jitter());
 		let (gossip_notifier, gossip_interval) =
-			AwarenessInterval::new(config.base_gossip_interval);
+			AwarenessInterval::new(config.base_gossip_interval, clock.clone(), jitter());
+		let (reconnect_notifier, reconnect_interval) =
+			Interval::new(config.rejoin.reconnect_interval, clock.clone(), None);
 
 		let tc = TimeoutCalculator::from(config.suspicion);
 		let state = State {
 			ping_interval: config.ping.base_interval,
-			node_count: node_count.try_into().unwrap_or(MAX_NON_ZERO_U32),
+			node_count,
 		};
 
-		let (suspicion_timeout, suspicion_timers) =
-			SuspicionTimers::new(config.ping.base_interval, tc, state);
-		let (ping_timeout, ping_timers) = PingTimers::new(config.ping.base_timeout);
+		let (timeouts, timer_driver, timers) = Timers::new(clock.clone());
+		let suspicion_timers = SuspicionTimers::new(
+			config.ping.base_interval,
+			tc,
+			state,
+			metrics,
+			timers.clone(),
+		);
+		let ping_timers = PingTimers::new(
+			config.ping.base_timeout,
+			config.ping.adaptive_timeout,
+			timers.clone(),
+		);
+		let rejoin_timer = RejoinTimer::new(config.rejoin, timers);
 
 		let e = SchedulerEvents {
 			sync_notifier,
 			ping_notifier,
 			gossip_notifier,
-			suspicion_timeout,
-			ping_timeout,
+			reconnect_notifier,
+			timeouts,
+			next_source: 0,
 		};
 
 		let s = Self {
 			sync_interval,
 			ping_interval,
 			gossip_interval,
+			reconnect_interval,
 			ping_timers,
 			suspicion_timers,
+			rejoin_timer,
+			_timer_driver: timer_driver,
+			clock,
 		};
 
 		(e, s)
 	}
 
+	/// Applies a [HealthEvent] to the local health. If the awareness score changed, the intervals and
+	/// timeouts are scaled by the new score and the [EventHandler] is informed.
+	pub(crate) fn update_health<E: EventHandler>(
+		&mut self,
+		awareness: &mut Awareness,
+		event: HealthEvent,
+		handler: &mut E,
+	) {
+		if let Some(score) = awareness.apply(event) {
+			self.update_awareness(score);
+			handler.awareness(score, awareness.max());
+		}
+	}
+
 	fn update_awareness(&mut self, awareness: NonZeroU32) {
 		self.gossip_interval.update(awareness);
 		let ping_interval = self.ping_interval.update(awareness);
@@ -101,10 +223,418 @@ impl Scheduler {
 		self.suspicion_timers.update_ping_interval(ping_interval);
 	}
 
-	fn update_node_count(&mut self, node_count: NonZeroUsize) {
+	/// Applies new timing parameters without restarting the scheduler.
+	/// Running timers and intervals are rebuilt using the new parameters.
+	pub(crate) fn reconfigure(&mut self, config: Reconfiguration) {
+		if let Some(d) = config.gossip_interval {
+			self.gossip_interval.set_base_interval(d);
+		}
+
+		if let Some(d) = config.sync_interval {
+			self.sync_interval.set_base_interval(d);
+		}
+
+		if let Some(d) = config.ping_timeout {
+			self.ping_timers.update_base_timeout(d);
+		}
+
+		if let Some(suspicion) = config.suspicion {
+			self.suspicion_timers
+				.update_calculator(TimeoutCalculator::from(suspicion));
+		}
+
+		if let Some(d) = config.ping_interval {
+			let ping_interval = self.ping_interval.set_base_interval(d);
+			self.suspicion_timers.update_ping_interval(ping_interval);
+		}
+	}
+
+	/// Returns the [Clock] driving the intervals and timers.
+	pub(crate) fn clock(&self) -> Arc<dyn Clock> {
+		self.clock.clone()
+	}
+
+	/// Starts the timer of a direct or indirect ping to a node with the given [Rtt].
+	pub(crate) fn start_ping_timer(&mut self, sequence: u64, rtt: Option<Rtt>) {
+		self.ping_timers.start_normal(sequence, rtt);
+	}
+
+	/// Starts the timer after which a `nack` is sent for a ping request to a node with the given [Rtt].
+	pub(crate) fn start_nack_timer(&mut self, sequence: u64, rtt: Option<Rtt>) {
+		self.ping_timers.start_nack(sequence, rtt);
+	}
+
+	/// Starts the timer after which a ping request fails, once the `nack` has been sent.
+	pub(crate) fn start_grace_timer(&mut self, sequence: u64) {
+		self.ping_timers.start_grace(sequence);
+	}
+
+	/// Stops the timer of an acked or failed ping.
+	pub(crate) fn stop_ping_timer(&mut self, sequence: u64) {
+		self.ping_timers.remove(&sequence);
+	}
+
+	/// Returns the timeout of the running ping timer for `sequence`.
+	pub(crate) fn ping_timeout(&self, sequence: u64) -> Option<Duration> {
+		self.ping_timers.timeout(sequence)
+	}
+
+	/// Starts the suspicion timer of a node, which sends the [KillRequest] once it expires.
+	pub(crate) fn start_suspicion(&mut self, kill_req: KillRequest) {
+		self.suspicion_timers.start(kill_req);
+	}
+
+	/// Shrinks the timeout of the suspicion about `addr` after it has been confirmed by other nodes.
+	pub(crate) fn update_suspectors(&mut self, addr: &SocketAddr, suspectors: NonZeroUsize) {
+		self.suspicion_timers.update_suspectors(addr, suspectors);
+	}
+
+	/// Stops the suspicion timer of a node.
+	pub(crate) fn stop_suspicion(&mut self, addr: &SocketAddr) {
+		self.suspicion_timers.remove(addr);
+	}
+
+	/// Returns the current timeout of the suspicion about `addr` and the time it expires.
+	/// Returns [None] if no suspicion timer is running for `addr`.
+	pub(crate) fn suspicion_timeout(&self, addr: &SocketAddr) -> Option<(Duration, Instant)> {
+		self.suspicion_timers.timeout(addr)
+	}
+
+	/// Starts or stops the rejoin attempts depending on the [Isolation] of the node.
+	pub(crate) fn update_isolation(&mut self, isolation: Isolation) {
+		self.rejoin_timer.update(isolation);
+	}
+
+	/// Schedules the next rejoin attempt after the last one failed.
+	pub(crate) fn rejoin_failed(&mut self) {
+		self.rejoin_timer.attempted();
+	}
+
+	/// Scales the sync interval and the suspicion timeouts by the amount of nodes in the cluster.
+	pub(crate) fn update_node_count(&mut self, node_count: NonZeroUsize) {
 		let node_count = node_count.try_into().unwrap_or(MAX_NON_ZERO_U32);
 
 		self.sync_interval.update(node_count);
 		self.suspicion_timers.update_node_count(node_count);
 	}
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::collections::HashSet;
+	use std::future::poll_fn;
+	use std::num::NonZeroU32;
+
+	use rand::rngs::SmallRng;
+
+	use super::*;
+	use crate::clock::{Clock, ManualClock};
+	use crate::{
+		FixedTimeout, JitterConfig, PingSchedulerConfig, ReclaimConfig, RejoinConfig,
+		SuspicionConfig, SuspicionTimeout, SyncSchedulerConfig,
+	};
+
+	/// Returns a [SchedulerConfig] without jitter driven by `clock`. Pings are sent every second and
+	/// time out after 100ms.
+	pub(crate) fn config(clock: Arc<dyn Clock>) -> SchedulerConfig {
+		SchedulerConfig {
+			ping: PingSchedulerConfig {
+				base_interval: Duration::from_secs(1),
+				base_timeout: Duration::from_millis(100),
+				adaptive_timeout: None,
+			},
+			sync: SyncSchedulerConfig {
+				base_interval: Duration::from_secs(30),
+				scale: NonZeroU32::new(4).unwrap(),
+			},
+			base_gossip_interval: Duration::from_millis(200),
+			suspicion: Arc::new(SuspicionConfig {
+				alpha: 1.0,
+				beta: 5.0,
+				k: NonZeroU32::new(3).unwrap(),
+			}),
+			reclaim: ReclaimConfig {
+				dead: Duration::from_secs(60),
+				left: Duration::from_secs(60),
+			},
+			rejoin: RejoinConfig {
+				alone_threshold: Duration::from_secs(10),
+				min_backoff: Duration::from_secs(1),
+				max_backoff: Duration::from_secs(8),
+				reconnect_interval: Duration::from_secs(30),
+			},
+			clock: Some(clock),
+			jitter: JitterConfig {
+				factor: 0.0,
+				spread_first_tick: false,
+			},
+		}
+	}
+
+	/// Records the awareness scores passed to [EventHandler::awareness].
+	#[derive(Default)]
+	struct AwarenessRecorder(Vec<(u32, u32)>);
+
+	impl EventHandler for AwarenessRecorder {
+		fn awareness(&mut self, awareness: NonZeroU32, max: NonZeroU32) {
+			self.0.push((awareness.get(), max.get()));
+		}
+	}
+
+	fn scheduler(manual: &Arc<ManualClock>, node_count: usize) -> (SchedulerEvents, Scheduler) {
+		let mut rng = SmallRng::seed_from_u64(0);
+		Scheduler::new(
+			config(manual.clone()),
+			NonZeroUsize::new(node_count).unwrap(),
+			Arc::new(Metrics::default()),
+			&mut rng,
+		)
+	}
+
+	/// Advances the clock by `d` and returns the events which are ready afterwards.
+	pub(crate) async fn advance(
+		manual: &ManualClock,
+		events: &mut SchedulerEvents,
+		d: Duration,
+	) -> Vec<SchedulerEvent> {
+		manual.advance(d);
+		for _ in 0..10 {
+			tokio::task::yield_now().await;
+		}
+
+		let mut result = Vec::new();
+		while let Poll::Ready(Some(event)) =
+			poll_fn(|cx| Poll::Ready(Pin::new(&mut *events).poll_next(cx))).await
+		{
+			result.push(event);
+		}
+		result
+	}
+
+	#[tokio::test]
+	async fn sync_interval_is_scaled_by_the_initial_node_count() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		// 30s * (ceil(log2(16) - log2(4)) + 1) = 90s
+		let (mut events, _scheduler) = scheduler(&manual, 16);
+
+		let received = advance(&manual, &mut events, Duration::from_secs(30)).await;
+		assert!(!received
+			.iter()
+			.any(|e| matches!(e, SchedulerEvent::SyncInterval)));
+
+		let received = advance(&manual, &mut events, Duration::from_secs(60)).await;
+		assert!(received
+			.iter()
+			.any(|e| matches!(e, SchedulerEvent::SyncInterval)));
+	}
+
+	#[tokio::test]
+	async fn reconfigure_resets_running_intervals() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let (mut events, mut scheduler) = scheduler(&manual, 1);
+
+		scheduler.reconfigure(Reconfiguration {
+			ping_interval: Some(Duration::from_secs(3)),
+			gossip_interval: Some(Duration::from_millis(500)),
+			sync_interval: Some(Duration::from_secs(2)),
+			..Default::default()
+		});
+
+		let has = |events: &[SchedulerEvent], f: fn(&SchedulerEvent) -> bool| events.iter().any(f);
+		let gossip = |e: &SchedulerEvent| matches!(e, SchedulerEvent::GossipInterval);
+		let ping = |e: &SchedulerEvent| matches!(e, SchedulerEvent::PingInterval);
+		let sync = |e: &SchedulerEvent| matches!(e, SchedulerEvent::SyncInterval);
+
+		// the old gossip interval of 200ms has passed.
+		let received = advance(&manual, &mut events, Duration::from_millis(200)).await;
+		assert!(!has(&received, gossip));
+		let received = advance(&manual, &mut events, Duration::from_millis(300)).await;
+		assert!(has(&received, gossip));
+
+		// the old ping interval of 1s has passed.
+		let received = advance(&manual, &mut events, Duration::from_secs(1)).await;
+		assert!(!has(&received, ping));
+		assert!(!has(&received, sync));
+
+		let received = advance(&manual, &mut events, Duration::from_millis(500)).await;
+		assert!(has(&received, sync));
+		assert!(!has(&received, ping));
+
+		let received = advance(&manual, &mut events, Duration::from_secs(1)).await;
+		assert!(has(&received, ping));
+	}
+
+	#[tokio::test]
+	async fn reconfigure_resets_running_ping_timers() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let (mut events, mut scheduler) = scheduler(&manual, 1);
+
+		scheduler.start_ping_timer(1, None);
+		assert_eq!(scheduler.ping_timeout(1), Some(Duration::from_millis(100)));
+
+		scheduler.reconfigure(Reconfiguration {
+			ping_timeout: Some(Duration::from_millis(50)),
+			..Default::default()
+		});
+		assert_eq!(scheduler.ping_timeout(1), Some(Duration::from_millis(50)));
+
+		let received = advance(&manual, &mut events, Duration::from_millis(50)).await;
+		assert!(received
+			.iter()
+			.any(|e| matches!(e, SchedulerEvent::PingTimeout(1))));
+
+		// new timers use the new base timeout as well.
+		scheduler.start_ping_timer(2, None);
+		assert_eq!(scheduler.ping_timeout(2), Some(Duration::from_millis(50)));
+	}
+
+	/// Grows the timeout with the cluster size and every suspector.
+	#[derive(Debug)]
+	struct PerSuspector;
+
+	impl SuspicionTimeout for PerSuspector {
+		fn timeout(
+			&self,
+			node_count: NonZeroU32,
+			ping_interval: Duration,
+			suspectors: NonZeroU32,
+		) -> Duration {
+			ping_interval * node_count.get() * suspectors.get()
+		}
+	}
+
+	/// Starts a suspicion in a cluster of 3 nodes using `strategy` and returns its timeout and the time
+	/// it expires relative to the start.
+	fn suspicion_deadline(strategy: Arc<dyn SuspicionTimeout>) -> (Duration, Duration) {
+		let start = Instant::now();
+		let manual = Arc::new(ManualClock::new(start));
+		let mut config = config(manual.clone());
+		config.suspicion = strategy;
+
+		let mut rng = SmallRng::seed_from_u64(0);
+		let (_events, mut scheduler) = Scheduler::new(
+			config,
+			NonZeroUsize::new(3).unwrap(),
+			Arc::new(Metrics::default()),
+			&mut rng,
+		);
+
+		manual.advance(Duration::from_secs(1));
+		let addr = "127.0.0.1:1".parse().unwrap();
+		scheduler.start_suspicion(KillRequest {
+			addr,
+			incarnation: 0,
+		});
+
+		let (d, deadline) = scheduler.suspicion_timeout(&addr).unwrap();
+		(d, deadline - start)
+	}
+
+	#[tokio::test]
+	async fn fixed_suspicion_timeout() {
+		let (d, deadline) = suspicion_deadline(Arc::new(FixedTimeout(Duration::from_secs(7))));
+
+		assert_eq!(d, Duration::from_secs(7));
+		assert_eq!(deadline, Duration::from_secs(8));
+	}
+
+	#[tokio::test]
+	async fn custom_suspicion_timeout() {
+		let (d, deadline) = suspicion_deadline(Arc::new(PerSuspector));
+
+		// 1s ping interval * 3 nodes * 1 suspector
+		assert_eq!(d, Duration::from_secs(3));
+		assert_eq!(deadline, Duration::from_secs(4));
+	}
+
+	#[tokio::test]
+	async fn health_changes_rescale_timers() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let mut rng = SmallRng::seed_from_u64(0);
+		let (_events, mut scheduler) = Scheduler::new(
+			config(manual.clone()),
+			NonZeroUsize::new(1).unwrap(),
+			Arc::new(Metrics::default()),
+			&mut rng,
+		);
+
+		let mut awareness = Awareness::new(NonZeroU32::new(4).unwrap(), 0);
+		let mut handler = AwarenessRecorder::default();
+		let addr = "127.0.0.1:1".parse().unwrap();
+
+		scheduler.start_ping_timer(1, None);
+		scheduler.start_suspicion(KillRequest {
+			addr,
+			incarnation: 0,
+		});
+		assert_eq!(scheduler.ping_timeout(1), Some(Duration::from_millis(100)));
+		assert_eq!(
+			scheduler.suspicion_timeout(&addr).unwrap().0,
+			Duration::from_secs(5)
+		);
+
+		let failed = HealthEvent::ProbeFailed {
+			expected_nacks: 3,
+			nacks: 1,
+		};
+		scheduler.update_health(&mut awareness, failed, &mut handler);
+		assert_eq!(handler.0, vec![(3, 4)]);
+		assert_eq!(scheduler.ping_timeout(1), Some(Duration::from_millis(300)));
+		assert_eq!(
+			scheduler.suspicion_timeout(&addr).unwrap().0,
+			Duration::from_secs(15)
+		);
+
+		scheduler.update_health(&mut awareness, HealthEvent::ProbeSucceeded, &mut handler);
+		scheduler.update_health(&mut awareness, HealthEvent::ProbeSucceeded, &mut handler);
+		// the score is already at its lower bound, so the handler is not invoked.
+		scheduler.update_health(&mut awareness, HealthEvent::ProbeSucceeded, &mut handler);
+		assert_eq!(handler.0, vec![(3, 4), (2, 4), (1, 4)]);
+		assert_eq!(scheduler.ping_timeout(1), Some(Duration::from_millis(100)));
+	}
+
+	#[tokio::test]
+	async fn events_are_polled_fairly() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let clock: Arc<dyn Clock> = manual.clone();
+		let d = Duration::from_secs(1);
+
+		let (sync_notifier, _sync) = Interval::new(d, clock.clone(), None);
+		let (ping_notifier, _ping) = Interval::new(d, clock.clone(), None);
+		let (gossip_notifier, _gossip) = Interval::new(d, clock.clone(), None);
+		let (reconnect_notifier, _reconnect) = Interval::new(d, clock.clone(), None);
+		let (timeouts, _driver, timers) = Timers::new(clock);
+
+		let mut events = SchedulerEvents {
+			sync_notifier,
+			ping_notifier,
+			gossip_notifier,
+			reconnect_notifier,
+			timeouts,
+			next_source: 0,
+		};
+
+		for round in 0..3 {
+			// the timeouts keep the channel busy, but must not starve the intervals.
+			for i in 0..10 {
+				let seq = round * 10 + i;
+				timers.start(timer::TimerKey::Ping(seq), d, Timeout::Ping(seq));
+			}
+			manual.advance(d);
+			for _ in 0..10 {
+				tokio::task::yield_now().await;
+			}
+
+			let mut received = HashSet::new();
+			for _ in 0..SchedulerEvents::SOURCES {
+				let event = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await;
+				received.insert(std::mem::discriminant(&event.unwrap()));
+				// let the timer driver refill the channel.
+				tokio::task::yield_now().await;
+			}
+
+			// every source was ready, so each of them must have been polled once.
+			assert_eq!(received.len(), SchedulerEvents::SOURCES);
+		}
+	}
+}
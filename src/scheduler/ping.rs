@@ -2,18 +2,19 @@ use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::time::Duration;
 
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use crate::rtt::Rtt;
+use crate::AdaptiveTimeoutConfig;
 
-use super::timer::{Output, Timer};
+use super::timer::{Timeout, TimerKey, Timers};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum PingTimer {
 	/// Used for direct and indirect pings.
-	Normal(Timer),
+	Normal,
 	/// Used for ping-requests. Uses 80% of the normal Timeout.
-	Nack(Timer),
+	Nack,
 	/// Used for ping-requests after a [PingTimer::Nack]. Uses 20% of the normal Timeout.
-	Grace(Timer),
+	Grace,
 }
 
 impl PingTimer {
@@ -21,11 +22,11 @@ impl PingTimer {
 	const NACK_MUL: f64 = 0.80;
 	const GRACE_MUL: f64 = 0.20;
 
-	fn update(&mut self, normal_timeout: Duration, out: Output<u64>) {
+	fn multiplier(self) -> f64 {
 		match self {
-			PingTimer::Normal(timer) => timer.reset(normal_timeout.mul_f64(Self::NORMAL_MUL), out),
-			PingTimer::Nack(timer) => timer.reset(normal_timeout.mul_f64(Self::NACK_MUL), out),
-			PingTimer::Grace(timer) => timer.reset(normal_timeout.mul_f64(Self::GRACE_MUL), out),
+			PingTimer::Normal => Self::NORMAL_MUL,
+			PingTimer::Nack => Self::NACK_MUL,
+			PingTimer::Grace => Self::GRACE_MUL,
 		}
 	}
 }
@@ -33,75 +34,152 @@ impl PingTimer {
 #[derive(Debug)]
 pub(super) struct PingTimers {
 	base_timeout: Duration,
-	map: HashMap<u64, PingTimer>,
-	tx: Sender<u64>,
+	adaptive: Option<AdaptiveTimeoutConfig>,
+	/// The timer and the [Rtt] of the target of each probe.
+	map: HashMap<u64, (PingTimer, Option<Rtt>)>,
+	timers: Timers,
 
 	awareness: NonZeroU32,
 }
 
 impl PingTimers {
-	pub(super) fn new(base_timeout: Duration) -> (Receiver<u64>, Self) {
-		let (tx, rx) = channel(1);
-		let this = Self {
+	pub(super) fn new(
+		base_timeout: Duration,
+		adaptive: Option<AdaptiveTimeoutConfig>,
+		timers: Timers,
+	) -> Self {
+		Self {
 			base_timeout,
+			adaptive,
 			map: HashMap::new(),
-			tx,
+			timers,
 			awareness: NonZeroU32::new(1).unwrap(),
-		};
-		(rx, this)
+		}
 	}
 
-	#[inline]
-	fn calc_normal_timeout(&self) -> Duration {
-		self.awareness.get() * self.base_timeout
+	/// Returns the timeout of a [PingTimer]. Uses the [Rtt] of the target if adaptive timeouts are
+	/// enabled, bounded by the min timeout and the awareness scaled base timeout of the [PingTimer].
+	fn calc_timeout(&self, timer: PingTimer, rtt: Option<&Rtt>) -> Duration {
+		let awareness = self.awareness.get();
+		let max = (awareness * self.base_timeout).mul_f64(timer.multiplier());
+
+		match (self.adaptive, rtt) {
+			(Some(config), Some(rtt)) => {
+				let d = (awareness * rtt.rto()).mul_f64(timer.multiplier());
+				d.clamp(config.min.min(max), max)
+			}
+			_ => max,
+		}
 	}
 
-	#[inline]
-	fn make_timer(&self, sequence: u64, multiplier: f64) -> Timer {
-		let out = Output {
-			value: sequence,
-			tx: self.tx.clone(),
-		};
+	fn start(&mut self, sequence: u64, timer: PingTimer, rtt: Option<Rtt>) {
+		let d = self.calc_timeout(timer, rtt.as_ref());
 
-		let d = self.calc_normal_timeout().mul_f64(multiplier);
-
-		Timer::new(d, out)
+		self.timers
+			.start(TimerKey::Ping(sequence), d, Timeout::Ping(sequence));
+		self.map.insert(sequence, (timer, rtt));
 	}
 
-	pub(super) fn start_normal(&mut self, sequence: u64) {
-		let timer = self.make_timer(sequence, PingTimer::NORMAL_MUL);
-		let timer = PingTimer::Normal(timer);
-		self.map.insert(sequence, timer);
+	/// Starts the timer of a direct or indirect ping to a node with the given [Rtt].
+	pub(super) fn start_normal(&mut self, sequence: u64, rtt: Option<Rtt>) {
+		self.start(sequence, PingTimer::Normal, rtt);
 	}
 
-	pub(super) fn start_nack(&mut self, sequence: u64) {
-		let timer = self.make_timer(sequence, PingTimer::NACK_MUL);
-		let timer = PingTimer::Nack(timer);
-		self.map.insert(sequence, timer);
+	pub(super) fn start_nack(&mut self, sequence: u64, rtt: Option<Rtt>) {
+		self.start(sequence, PingTimer::Nack, rtt);
 	}
 
+	/// Starts the grace timer after the [PingTimer::Nack] of the same ping request expired, using the
+	/// [Rtt] the nack timer was started with.
 	pub(super) fn start_grace(&mut self, sequence: u64) {
-		let timer = self.make_timer(sequence, PingTimer::GRACE_MUL);
-		let timer = PingTimer::Grace(timer);
-		self.map.insert(sequence, timer);
+		let rtt = self.map.get(&sequence).and_then(|(_, rtt)| *rtt);
+		self.start(sequence, PingTimer::Grace, rtt);
+	}
+
+	/// Returns the timeout of the running timer for `sequence`.
+	pub(super) fn timeout(&self, sequence: u64) -> Option<Duration> {
+		self.timers.get(&TimerKey::Ping(sequence)).map(|(d, _)| d)
 	}
 
 	pub(super) fn remove(&mut self, sequence: &u64) {
 		self.map.remove(sequence);
+		self.timers.stop(&TimerKey::Ping(*sequence));
 	}
 
 	pub(super) fn update_awareness(&mut self, awareness: NonZeroU32) {
 		self.awareness = awareness;
+		self.reset_timers();
+	}
 
-		let d = self.calc_normal_timeout();
-
-		for (&sequence, ping) in self.map.iter_mut() {
-			let out = Output {
-				value: sequence,
-				tx: self.tx.clone(),
-			};
+	pub(super) fn update_base_timeout(&mut self, base_timeout: Duration) {
+		self.base_timeout = base_timeout;
+		self.reset_timers();
+	}
 
-			ping.update(d, out);
+	fn reset_timers(&mut self) {
+		for (&sequence, (timer, rtt)) in self.map.iter() {
+			let d = self.calc_timeout(*timer, rtt.as_ref());
+			self.timers.reset(&TimerKey::Ping(sequence), d);
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use super::*;
+	use crate::clock::TokioClock;
+
+	fn ms(ms: u64) -> Duration {
+		Duration::from_millis(ms)
+	}
+
+	fn timeout(timers: &PingTimers, sequence: u64) -> Duration {
+		timers.timers.get(&TimerKey::Ping(sequence)).unwrap().0
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn adaptive_timeouts_follow_rtt() {
+		let (_rx, _handle, timers) = Timers::new(Arc::new(TokioClock));
+		let config = AdaptiveTimeoutConfig { min: ms(20) };
+		let mut timers = PingTimers::new(ms(500), Some(config), timers);
+
+		// srtt + 4 * rttvar = 10ms + 20ms, between the bounds.
+		timers.start_normal(1, Some(Rtt::new(ms(10))));
+		// below the min timeout.
+		timers.start_normal(2, Some(Rtt::new(ms(2))));
+		// above the base timeout.
+		timers.start_normal(3, Some(Rtt::new(ms(200))));
+		timers.start_normal(4, None);
+		timers.start_nack(5, Some(Rtt::new(ms(10))));
+		timers.start_nack(6, Some(Rtt::new(ms(10))));
+		// 20% of 30ms is below the min timeout.
+		timers.start_grace(6);
+
+		assert_eq!(timeout(&timers, 1), ms(30));
+		assert_eq!(timeout(&timers, 2), ms(20));
+		assert_eq!(timeout(&timers, 3), ms(500));
+		assert_eq!(timeout(&timers, 4), ms(500));
+		assert_eq!(timeout(&timers, 5), ms(24));
+		assert_eq!(timeout(&timers, 6), ms(20));
+
+		timers.update_awareness(NonZeroU32::new(2).unwrap());
+		assert_eq!(timeout(&timers, 1), ms(60));
+		assert_eq!(timeout(&timers, 3), ms(1000));
+		assert_eq!(timeout(&timers, 4), ms(1000));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn fixed_timeouts_ignore_rtt() {
+		let (_rx, _handle, timers) = Timers::new(Arc::new(TokioClock));
+		let mut timers = PingTimers::new(ms(500), None, timers);
+
+		timers.start_normal(1, Some(Rtt::new(ms(10))));
+		timers.start_nack(2, Some(Rtt::new(ms(10))));
+		timers.start_grace(2);
+
+		assert_eq!(timeout(&timers, 1), ms(500));
+		assert_eq!(timeout(&timers, 2), ms(100));
+	}
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use crate::node_set::Isolation;
+use crate::RejoinConfig;
+
+use super::timer::{Timeout, TimerKey, Timers};
+
+/// Schedules attempts to rejoin the cluster using the seeds while the node is isolated.
+///
+/// The first attempt is made right away if all known peers are down, or after
+/// [RejoinConfig::alone_threshold] if the node does not know any peers. Each further attempt
+/// doubles the delay up to [RejoinConfig::max_backoff].
+pub(super) struct RejoinTimer {
+	config: RejoinConfig,
+	backoff: Duration,
+	/// `true` from the start of the timer until the node is connected or the next attempt is scheduled.
+	scheduled: bool,
+	timers: Timers,
+}
+
+impl RejoinTimer {
+	pub(super) fn new(config: RejoinConfig, timers: Timers) -> Self {
+		Self {
+			backoff: config.min_backoff,
+			config,
+			scheduled: false,
+			timers,
+		}
+	}
+
+	fn start(&mut self, d: Duration) {
+		self.timers.start(TimerKey::Rejoin, d, Timeout::Rejoin);
+		self.scheduled = true;
+	}
+
+	/// Starts the timer if the node became isolated and stops it once the node is connected again.
+	pub(super) fn update(&mut self, isolation: Isolation) {
+		match isolation {
+			Isolation::Connected => {
+				self.timers.stop(&TimerKey::Rejoin);
+				self.scheduled = false;
+				self.backoff = self.config.min_backoff;
+			}
+			_ if self.scheduled => {}
+			Isolation::PeersDown => self.start(Duration::from_nanos(0)),
+			Isolation::Alone => self.start(self.config.alone_threshold),
+		}
+	}
+
+	/// Schedules the next attempt after a rejoin attempt failed to connect the node.
+	pub(super) fn attempted(&mut self) {
+		let d = self.backoff;
+		self.backoff = next_backoff(d, self.config.max_backoff);
+		self.start(d);
+	}
+}
+
+fn next_backoff(current: Duration, max: Duration) -> Duration {
+	current.checked_mul(2).unwrap_or(max).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use tokio::time::Instant;
+
+	use super::*;
+	use crate::clock::ManualClock;
+
+	fn secs(secs: u64) -> Duration {
+		Duration::from_secs(secs)
+	}
+
+	fn timer() -> (Timers, RejoinTimer) {
+		let config = RejoinConfig {
+			alone_threshold: secs(10),
+			min_backoff: secs(1),
+			max_backoff: secs(8),
+			reconnect_interval: secs(30),
+		};
+		let clock = Arc::new(ManualClock::new(Instant::now()));
+		let (_rx, _handle, timers) = Timers::new(clock);
+
+		(timers.clone(), RejoinTimer::new(config, timers))
+	}
+
+	fn timeout(timers: &Timers) -> Option<Duration> {
+		timers.get(&TimerKey::Rejoin).map(|(d, _)| d)
+	}
+
+	#[test]
+	fn backoff() {
+		let max = Duration::from_secs(10);
+
+		let mut d = Duration::from_secs(1);
+		let mut result = Vec::new();
+		for _ in 0..6 {
+			d = next_backoff(d, max);
+			result.push(d.as_secs());
+		}
+
+		assert_eq!(result, vec![2, 4, 8, 10, 10, 10]);
+
+		let max = Duration::MAX;
+		assert_eq!(next_backoff(Duration::MAX / 2 + secs(1), max), max);
+	}
+
+	#[tokio::test]
+	async fn starts_once_isolated() {
+		let (timers, mut rejoin) = timer();
+
+		rejoin.update(Isolation::Alone);
+		assert_eq!(timeout(&timers), Some(secs(10)));
+
+		// a running timer is not restarted.
+		rejoin.update(Isolation::PeersDown);
+		assert_eq!(timeout(&timers), Some(secs(10)));
+
+		rejoin.update(Isolation::Connected);
+		assert_eq!(timeout(&timers), None);
+
+		rejoin.update(Isolation::PeersDown);
+		assert_eq!(timeout(&timers), Some(secs(0)));
+	}
+
+	#[tokio::test]
+	async fn backoff_is_capped_and_reset_on_success() {
+		let (timers, mut rejoin) = timer();
+		rejoin.update(Isolation::PeersDown);
+
+		let mut result = Vec::new();
+		for _ in 0..6 {
+			rejoin.attempted();
+			result.push(timeout(&timers).unwrap().as_secs());
+		}
+		assert_eq!(result, vec![1, 2, 4, 8, 8, 8]);
+
+		rejoin.update(Isolation::Connected);
+		rejoin.update(Isolation::PeersDown);
+		rejoin.attempted();
+		assert_eq!(timeout(&timers), Some(secs(1)));
+	}
+}
@@ -2,14 +2,16 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::net::SocketAddr;
 use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::Arc;
 use std::time::Duration;
 
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::Instant;
 
 use crate::consts::{MAX_NON_ZERO_U32, MIN_NON_ZERO_U32};
-use crate::SuspicionConfig;
+use crate::metrics::Metrics;
+use crate::{SuspicionConfig, SuspicionTimeout};
 
-use super::timer::{Output, Timer};
+use super::timer::{Timeout, TimerKey, Timers};
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct KillRequest {
@@ -23,61 +25,38 @@ pub(crate) struct State {
 	pub(crate) node_count: NonZeroU32,
 }
 
-#[derive(Debug)]
-pub(crate) struct TimeoutCalculator {
-	alpha: f64,
-	beta: f64,
-	k: NonZeroU32,
+/// Computes suspicion timeouts using a [SuspicionTimeout] strategy.
+#[derive(Debug, Clone)]
+pub(crate) struct TimeoutCalculator(Arc<dyn SuspicionTimeout>);
+
+impl From<Arc<dyn SuspicionTimeout>> for TimeoutCalculator {
+	fn from(strategy: Arc<dyn SuspicionTimeout>) -> Self {
+		Self(strategy)
+	}
 }
 
 impl From<SuspicionConfig> for TimeoutCalculator {
 	fn from(config: SuspicionConfig) -> Self {
-		Self {
-			alpha: config.alpha,
-			beta: config.beta,
-			k: config.k,
-		}
+		Self(Arc::new(config))
 	}
 }
 
 impl TimeoutCalculator {
-	fn min_max(&self, state: &State) -> (Duration, Duration) {
-		let node_count: f64 = state.node_count.get().into();
-		let scale = f64::max(1.0, self.alpha * node_count.log10());
-		let min = state.ping_interval.mul_f64(scale);
-
-		let max = min.mul_f64(self.beta);
-
-		(min, max)
-	}
-
-	fn timeout(&self, min: Duration, max: Duration, c: NonZeroU32) -> Duration {
-		let min = min.as_secs_f64();
-		let max = max.as_secs_f64();
-
-		let c = c.get();
-		let k = self.k.get() + 1; // + 1 to ensure the divisor is never 0.
-
-		let c: f64 = c.into();
-		let k: f64 = k.into();
-
-		let frac = c.log10() / k.log10();
-		let f = max - (max - min) * frac;
-
-		let duration_secs = f64::max(min, f);
-		let duration_millis = (duration_secs * 1000f64).floor() as u64; // Round to millis.
-
-		Duration::from_millis(duration_millis)
+	fn timeout(&self, state: &State, suspectors: NonZeroU32) -> Duration {
+		self.0
+			.timeout(state.node_count, state.ping_interval, suspectors)
 	}
 }
 
 pub(crate) struct SuspicionTimers {
 	base_timeout: Duration,
-	map: HashMap<SocketAddr, (Timer, KillRequest, NonZeroU32)>,
-	tx: Sender<KillRequest>,
+	map: HashMap<SocketAddr, NonZeroU32>,
+	timers: Timers,
 
 	calc: TimeoutCalculator,
 	state: State,
+
+	metrics: Arc<Metrics>,
 }
 
 impl SuspicionTimers {
@@ -85,35 +64,32 @@ impl SuspicionTimers {
 		base_timeout: Duration,
 		calc: TimeoutCalculator,
 		state: State,
-	) -> (Receiver<KillRequest>, Self) {
-		let (tx, rx) = channel(1);
-		let this = Self {
+		metrics: Arc<Metrics>,
+		timers: Timers,
+	) -> Self {
+		Self {
 			base_timeout,
 			map: HashMap::new(),
-			tx,
+			timers,
 			calc,
 			state,
-		};
-		(rx, this)
+			metrics,
+		}
 	}
 
 	pub(crate) fn start(&mut self, kill_req: KillRequest) {
-		let (min, max) = self.calc.min_max(&self.state);
-		let d = self.calc.timeout(min, max, MIN_NON_ZERO_U32);
+		let d = self.calc.timeout(&self.state, MIN_NON_ZERO_U32);
 
-		let out = Output {
-			value: kill_req,
-			tx: self.tx.clone(),
-		};
-
-		let timer = Timer::new(d, out);
+		let key = TimerKey::Suspicion(kill_req.addr);
+		self.timers.start(key, d, Timeout::Suspicion(kill_req));
 
-		self.map
-			.insert(kill_req.addr, (timer, kill_req, MIN_NON_ZERO_U32));
+		self.map.insert(kill_req.addr, MIN_NON_ZERO_U32);
+		self.metrics.suspicions_started.inc();
 	}
 
 	pub(crate) fn remove(&mut self, addr: &SocketAddr) {
 		self.map.remove(addr);
+		self.timers.stop(&TimerKey::Suspicion(*addr));
 	}
 
 	pub(super) fn update_node_count(&mut self, node_count: NonZeroU32) {
@@ -128,69 +104,83 @@ impl SuspicionTimers {
 		self.reset_timers();
 	}
 
+	pub(super) fn update_calculator(&mut self, calc: TimeoutCalculator) {
+		self.calc = calc;
+
+		self.reset_timers();
+	}
+
 	pub(crate) fn update_suspectors(&mut self, addr: &SocketAddr, suspectors: NonZeroUsize) {
 		let suspectors = suspectors.try_into().unwrap_or(MAX_NON_ZERO_U32);
 
-		if let Some((timer, kill_req, s)) = self.map.get_mut(addr) {
+		if let Some(s) = self.map.get_mut(addr) {
 			*s = suspectors;
+			self.metrics.suspicions_confirmed.inc();
 
-			let (min, max) = self.calc.min_max(&self.state);
-			let d = self.calc.timeout(min, max, suspectors);
-
-			let out = Output {
-				value: *kill_req,
-				tx: self.tx.clone(),
-			};
-
-			timer.reset(d, out);
+			let d = self.calc.timeout(&self.state, suspectors);
+			self.timers.reset(&TimerKey::Suspicion(*addr), d);
 		}
 	}
 
-	fn reset_timers(&mut self) {
-		for (timer, kill_req, suspectors) in self.map.values_mut() {
-			let (min, max) = self.calc.min_max(&self.state);
-			let d = self.calc.timeout(min, max, *suspectors);
-
-			let out = Output {
-				value: *kill_req,
-				tx: self.tx.clone(),
-			};
+	/// Returns the current timeout of the suspicion about `addr` and the time it expires.
+	pub(crate) fn timeout(&self, addr: &SocketAddr) -> Option<(Duration, Instant)> {
+		self.timers.get(&TimerKey::Suspicion(*addr))
+	}
 
-			timer.reset(d, out);
+	fn reset_timers(&mut self) {
+		for (addr, suspectors) in self.map.iter() {
+			let d = self.calc.timeout(&self.state, *suspectors);
+			self.timers.reset(&TimerKey::Suspicion(*addr), d);
 		}
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use std::convert::TryInto;
-
 	use super::*;
+	use crate::clock::TokioClock;
 
-	#[test]
-	fn calc_timeout() {
-		let t = TimeoutCalculator {
+	#[tokio::test(start_paused = true)]
+	async fn timeout_shrinks_with_suspectors() {
+		let calc = TimeoutCalculator::from(SuspicionConfig {
 			alpha: 1.0,
-			beta: 1.0,
-			k: NonZeroU32::new(3).unwrap(), // <- only this is important
+			beta: 15.0,
+			k: NonZeroU32::new(3).unwrap(),
+		});
+		let state = State {
+			ping_interval: Duration::from_secs(2),
+			node_count: NonZeroU32::new(1).unwrap(),
 		};
-
-		let cases = vec![
-			(1, Duration::from_secs(30)),
-			(2, Duration::from_secs(16)),
-			(3, Duration::from_millis(7810)),
-			(4, Duration::from_secs(2)),
-			(5, Duration::from_secs(2)),
-			(6, Duration::from_secs(2)),
-		];
-
-		for (c, expected) in cases {
-			let result = t.timeout(
-				Duration::from_secs(2),
-				Duration::from_secs(30),
-				c.try_into().unwrap(),
-			);
-			assert_eq!(result, expected);
-		}
+		let (_rx, _handle, timers) = Timers::new(Arc::new(TokioClock));
+		let mut timers = SuspicionTimers::new(
+			Duration::from_secs(1),
+			calc,
+			state,
+			Arc::new(Metrics::default()),
+			timers,
+		);
+
+		let addr = "127.0.0.1:1".parse().unwrap();
+		assert!(timers.timeout(&addr).is_none());
+
+		let started = Instant::now();
+		timers.start(KillRequest {
+			addr,
+			incarnation: 1,
+		});
+		assert_eq!(
+			timers.timeout(&addr),
+			Some((Duration::from_secs(30), started + Duration::from_secs(30)))
+		);
+
+		tokio::time::advance(Duration::from_secs(1)).await;
+		timers.update_suspectors(&addr, NonZeroUsize::new(2).unwrap());
+		assert_eq!(
+			timers.timeout(&addr),
+			Some((Duration::from_secs(16), started + Duration::from_secs(16)))
+		);
+
+		timers.remove(&addr);
+		assert!(timers.timeout(&addr).is_none());
 	}
 }
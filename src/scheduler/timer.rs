@@ -1,120 +1,374 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
-use tokio::sync::mpsc::Sender;
-use tokio::time::{sleep, Instant};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::Notify;
+use tokio::time::Instant;
 
+use crate::clock::Clock;
 use crate::handle::Handle;
 
-#[derive(Debug, Clone)]
-pub(crate) struct Output<T: Send> {
-	pub(crate) value: T,
-	pub(crate) tx: Sender<T>,
+use super::suspicion::KillRequest;
+
+/// Identifies a timer. Starting a timer with a key which is already in use replaces the old timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum TimerKey {
+	Ping(u64),
+	Suspicion(SocketAddr),
+	Rejoin,
 }
 
-impl<T: Send> Output<T> {
-	#[allow(unused_must_use)] // ignore the unused Result from `send`, since we don't care if the send succeeds.
-	async fn send(self) {
-		self.tx.send(self.value).await;
-	}
+/// The value sent once a timer expires.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Timeout {
+	Ping(u64),
+	Suspicion(KillRequest),
+	Rejoin,
 }
 
 #[derive(Debug)]
-pub(super) struct Timer {
+struct Entry {
 	started: Instant,
-	handle: Handle,
-	done: Arc<AtomicBool>, // check so we don't accidentally restart the timer again during a reset.
+	timeout: Duration,
+	value: Timeout,
+	/// The position of the entry in [Queue::heap].
+	pos: usize,
 }
 
-impl Timer {
-	pub(super) fn new<T>(d: Duration, out: Output<T>) -> Self
-	where
-		T: 'static + Send,
-	{
-		let done = Arc::new(AtomicBool::new(false));
+impl Entry {
+	#[inline]
+	fn deadline(&self) -> Instant {
+		self.started + self.timeout
+	}
+}
 
-		let handle = tokio::spawn(task(d, done.clone(), out));
+/// A delay queue implemented as an indexed binary min-heap ordered by deadline.
+///
+/// Each entry knows its position in the heap, so timers can be rescheduled and removed in
+/// `O(log n)` without allocating.
+#[derive(Debug, Default)]
+struct Queue {
+	heap: Vec<TimerKey>,
+	entries: HashMap<TimerKey, Entry>,
+}
 
-		Self {
-			started: Instant::now(),
-			handle: Handle::from(handle),
-			done,
+impl Queue {
+	fn insert(&mut self, key: TimerKey, started: Instant, timeout: Duration, value: Timeout) {
+		if let Some(entry) = self.entries.get_mut(&key) {
+			entry.started = started;
+			entry.timeout = timeout;
+			entry.value = value;
+
+			let pos = entry.pos;
+			self.fix(pos);
+			return;
 		}
+
+		let pos = self.heap.len();
+		self.heap.push(key);
+		self.entries.insert(
+			key,
+			Entry {
+				started,
+				timeout,
+				value,
+				pos,
+			},
+		);
+		self.sift_up(pos);
 	}
 
-	pub(super) fn reset<T>(&mut self, d: Duration, out: Output<T>)
-	where
-		T: 'static + Send,
-	{
-		let is_done = self.done.swap(true, Ordering::Acquire);
-		if is_done {
-			return;
+	/// Replaces the timeout of a timer, which is still measured from the time the timer was started.
+	/// Returns `false` if the timer does not exist or has already expired.
+	fn reschedule(&mut self, key: &TimerKey, timeout: Duration) -> bool {
+		let pos = match self.entries.get_mut(key) {
+			Some(entry) => {
+				entry.timeout = timeout;
+				entry.pos
+			}
+			None => return false,
+		};
+
+		self.fix(pos);
+		true
+	}
+
+	fn remove(&mut self, key: &TimerKey) -> Option<Entry> {
+		let pos = self.entries.get(key)?.pos;
+		let last = self.heap.len() - 1;
+
+		self.swap(pos, last);
+		self.heap.pop();
+		let entry = self.entries.remove(key);
+
+		if pos < self.heap.len() {
+			self.fix(pos);
 		}
 
-		self.handle.abort();
+		entry
+	}
 
-		self.done.store(false, Ordering::Release);
+	fn get(&self, key: &TimerKey) -> Option<&Entry> {
+		self.entries.get(key)
+	}
 
-		let now = Instant::now();
-		let passed = now - self.started;
+	fn next_deadline(&self) -> Option<Instant> {
+		self.heap.first().map(|key| self.entries[key].deadline())
+	}
 
-		// TODO: replace with `Duration::saturating_sub` once stable
-		let d = match d.checked_sub(passed) {
-			Some(d) => d,
-			None => Duration::from_nanos(0),
-		};
+	/// Removes and returns the value of the earliest timer if it expired at `now`.
+	fn pop_expired(&mut self, now: Instant) -> Option<Timeout> {
+		if self.next_deadline()? > now {
+			return None;
+		}
+
+		let key = self.heap[0];
+		self.remove(&key).map(|entry| entry.value)
+	}
+
+	#[inline]
+	fn deadline_at(&self, pos: usize) -> Instant {
+		self.entries[&self.heap[pos]].deadline()
+	}
+
+	fn swap(&mut self, a: usize, b: usize) {
+		self.heap.swap(a, b);
+		for pos in [a, b].iter().copied() {
+			if let Some(entry) = self.entries.get_mut(&self.heap[pos]) {
+				entry.pos = pos;
+			}
+		}
+	}
+
+	/// Restores the heap order after the deadline at `pos` changed.
+	fn fix(&mut self, pos: usize) {
+		let pos = self.sift_up(pos);
+		self.sift_down(pos);
+	}
+
+	fn sift_up(&mut self, mut pos: usize) -> usize {
+		while pos > 0 {
+			let parent = (pos - 1) / 2;
+			if self.deadline_at(pos) >= self.deadline_at(parent) {
+				break;
+			}
+
+			self.swap(pos, parent);
+			pos = parent;
+		}
+
+		pos
+	}
+
+	fn sift_down(&mut self, mut pos: usize) {
+		loop {
+			let mut smallest = pos;
+			for child in [2 * pos + 1, 2 * pos + 2].iter().copied() {
+				if child < self.heap.len() && self.deadline_at(child) < self.deadline_at(smallest) {
+					smallest = child;
+				}
+			}
+
+			if smallest == pos {
+				return;
+			}
+
+			self.swap(pos, smallest);
+			pos = smallest;
+		}
+	}
+}
 
-		let handle = tokio::spawn(task(d, self.done.clone(), out));
+#[derive(Debug)]
+struct Shared {
+	queue: Mutex<Queue>,
+	/// Wakes the driver task after the timers changed.
+	changed: Notify,
+}
+
+/// All protocol timers of a scheduler. The timers are kept in a single delay queue, which is driven by
+/// one task. Expired timers are sent as [Timeout]s.
+///
+/// [Timers] can be cloned to share the queue between the components of the scheduler.
+#[derive(Debug, Clone)]
+pub(super) struct Timers {
+	shared: Arc<Shared>,
+	clock: Arc<dyn Clock>,
+}
+
+impl Timers {
+	/// Returns the [Timers], the receiver of expired timers and the [Handle] of the driver task.
+	pub(super) fn new(clock: Arc<dyn Clock>) -> (Receiver<Timeout>, Handle, Self) {
+		let (tx, rx) = channel(1);
+		let shared = Arc::new(Shared {
+			queue: Mutex::new(Queue::default()),
+			changed: Notify::new(),
+		});
 
-		self.handle = Handle::from(handle);
+		let handle = tokio::spawn(drive(shared.clone(), clock.clone(), tx));
+
+		(rx, Handle::from(handle), Self { shared, clock })
+	}
+
+	fn queue(&self) -> MutexGuard<'_, Queue> {
+		self.shared.queue.lock().unwrap()
 	}
 
-	pub(super) fn stop(&self) {
-		self.handle.abort();
+	/// Starts a timer which sends `value` after `d`. Replaces the timer with the same key.
+	pub(super) fn start(&self, key: TimerKey, d: Duration, value: Timeout) {
+		self.queue().insert(key, self.clock.now(), d, value);
+		self.shared.changed.notify_one();
+	}
+
+	/// Replaces the timeout of a running timer. The new timeout is measured from the time the timer
+	/// was started, so the timer expires right away if the new timeout already passed.
+	pub(super) fn reset(&self, key: &TimerKey, d: Duration) {
+		if self.queue().reschedule(key, d) {
+			self.shared.changed.notify_one();
+		}
+	}
+
+	pub(super) fn stop(&self, key: &TimerKey) {
+		self.queue().remove(key);
+	}
+
+	/// Returns the timeout of a running timer and the time it expires.
+	pub(super) fn get(&self, key: &TimerKey) -> Option<(Duration, Instant)> {
+		self.queue()
+			.get(key)
+			.map(|entry| (entry.timeout, entry.deadline()))
 	}
 }
 
-async fn task<T>(d: Duration, done: Arc<AtomicBool>, out: Output<T>)
-where
-	T: 'static + Send,
-{
-	sleep(d).await;
+async fn drive(shared: Arc<Shared>, clock: Arc<dyn Clock>, tx: Sender<Timeout>) {
+	loop {
+		loop {
+			// the expired timer stays in the queue until the receiver has room for it, so stopping or
+			// restarting it while the channel is full discards the stale timeout.
+			let permit = match tx.reserve().await {
+				Ok(permit) => permit,
+				Err(_) => return,
+			};
 
-	let is_done = done.swap(true, Ordering::AcqRel);
+			let expired = shared.queue.lock().unwrap().pop_expired(clock.now());
 
-	if !is_done {
-		out.send().await;
+			match expired {
+				Some(timeout) => permit.send(timeout),
+				None => break,
+			}
+		}
+
+		let next = shared.queue.lock().unwrap().next_deadline();
+
+		match next {
+			Some(deadline) => {
+				tokio::select! {
+					_ = clock.sleep_until(deadline) => {},
+					_ = shared.changed.notified() => {},
+				}
+			}
+			None => shared.changed.notified().await,
+		}
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use tokio::sync::mpsc::channel;
-
 	use super::*;
+	use crate::clock::ManualClock;
+
+	fn ms(ms: u64) -> Duration {
+		Duration::from_millis(ms)
+	}
+
+	fn pop_all(q: &mut Queue, now: Instant) -> Vec<u64> {
+		let mut result = Vec::new();
+		while let Some(timeout) = q.pop_expired(now) {
+			match timeout {
+				Timeout::Ping(i) => result.push(i),
+				_ => unreachable!(),
+			}
+		}
+		result
+	}
+
+	#[test]
+	fn queue_orders_by_deadline() {
+		let mut q = Queue::default();
+		let now = Instant::now();
+
+		for (i, d) in [50, 10, 40, 20, 30].iter().enumerate() {
+			let i = i as u64;
+			q.insert(TimerKey::Ping(i), now, ms(*d), Timeout::Ping(i));
+		}
+
+		assert_eq!(q.next_deadline(), Some(now + ms(10)));
+		assert!(pop_all(&mut q, now).is_empty());
+
+		assert!(q.reschedule(&TimerKey::Ping(0), ms(5)));
+		assert!(!q.reschedule(&TimerKey::Ping(9), ms(5)));
+		assert!(q.remove(&TimerKey::Ping(2)).is_some());
+		assert!(q.remove(&TimerKey::Ping(2)).is_none());
+
+		assert_eq!(pop_all(&mut q, now + ms(20)), vec![0, 1, 3]);
+		assert_eq!(pop_all(&mut q, now + ms(100)), vec![4]);
+		assert_eq!(q.next_deadline(), None);
+	}
 
 	#[tokio::test]
-	async fn task_sends_output_if_done_is_false() {
-		let (tx, mut rx) = channel(1);
-		let out = Output { value: (), tx };
-		let done = Arc::new(AtomicBool::new(false));
+	async fn timers_follow_the_clock() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let clock: Arc<dyn Clock> = manual.clone();
+		let (mut rx, _handle, timers) = Timers::new(clock);
+
+		timers.start(TimerKey::Ping(1), ms(100), Timeout::Ping(1));
+		timers.start(TimerKey::Ping(2), ms(200), Timeout::Ping(2));
+		timers.start(TimerKey::Rejoin, ms(300), Timeout::Rejoin);
+
+		manual.advance(ms(50));
+		tokio::task::yield_now().await;
+		assert!(rx.try_recv().is_err());
+
+		timers.reset(&TimerKey::Ping(2), ms(60));
+		timers.stop(&TimerKey::Ping(1));
+		assert_eq!(timers.get(&TimerKey::Ping(2)).unwrap().0, ms(60));
 
-		task(Duration::from_nanos(0), done.clone(), out).await;
+		manual.advance(ms(10));
+		assert!(matches!(rx.recv().await, Some(Timeout::Ping(2))));
 
-		assert!(done.load(Ordering::Relaxed));
-		assert!(rx.recv().await.is_some());
+		manual.advance(ms(500));
+		assert!(matches!(rx.recv().await, Some(Timeout::Rejoin)));
+		assert!(timers.get(&TimerKey::Rejoin).is_none());
 	}
 
 	#[tokio::test]
-	async fn task_does_not_send_output_if_done_is_true() {
-		let (tx, mut rx) = channel(1);
-		let out = Output { value: (), tx };
-		let done = Arc::new(AtomicBool::new(true));
+	async fn blocked_timeouts_can_be_stopped() {
+		let manual = Arc::new(ManualClock::new(Instant::now()));
+		let clock: Arc<dyn Clock> = manual.clone();
+		let (mut rx, _handle, timers) = Timers::new(clock);
 
-		task(Duration::from_nanos(0), done.clone(), out).await;
+		timers.start(TimerKey::Ping(1), ms(10), Timeout::Ping(1));
+		timers.start(TimerKey::Ping(2), ms(20), Timeout::Ping(2));
+		timers.start(TimerKey::Rejoin, ms(30), Timeout::Rejoin);
+
+		// the first timeout fills the channel, the delivery of the others is blocked.
+		manual.advance(ms(50));
+		for _ in 0..10 {
+			tokio::task::yield_now().await;
+		}
+
+		timers.stop(&TimerKey::Ping(2));
+		timers.start(TimerKey::Rejoin, ms(100), Timeout::Rejoin);
+
+		assert!(matches!(rx.recv().await, Some(Timeout::Ping(1))));
+		for _ in 0..10 {
+			tokio::task::yield_now().await;
+		}
+		assert!(rx.try_recv().is_err());
 
-		assert!(done.load(Ordering::Relaxed));
-		assert!(rx.recv().await.is_none());
+		manual.advance(ms(100));
+		assert!(matches!(rx.recv().await, Some(Timeout::Rejoin)));
 	}
 }
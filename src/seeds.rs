@@ -0,0 +1,61 @@
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::net::lookup_host;
+
+/// The result of resolving the seeds of a [JoinConfig](crate::JoinConfig).
+#[derive(Debug, Default)]
+pub(crate) struct ResolvedSeeds {
+	/// Every resolved address in the order of the seeds, without duplicates.
+	pub(crate) addrs: Vec<SocketAddr>,
+	/// The seeds which could not be resolved.
+	pub(crate) errors: Vec<(String, io::Error)>,
+}
+
+/// Resolves `host:port` seeds through the system resolver. IP addresses are returned as they are.
+///
+/// Every returned `A` and `AAAA` record is kept, so each address behind a DNS name gets tried.
+/// The seeds should be resolved again for each join round, since the records may change.
+pub(crate) async fn resolve(seeds: &[String]) -> ResolvedSeeds {
+	let mut result = ResolvedSeeds::default();
+
+	for seed in seeds.iter() {
+		match lookup_host(seed.as_str()).await {
+			Ok(addrs) => {
+				for addr in addrs {
+					if !result.addrs.contains(&addr) {
+						result.addrs.push(addr);
+					}
+				}
+			}
+			Err(e) => result.errors.push((seed.clone(), e)),
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn resolve_seeds() {
+		let seeds = vec![
+			"127.0.0.2:7946".to_string(),
+			"localhost:7946".to_string(),
+			"127.0.0.2:7946".to_string(),
+			"missing-port".to_string(),
+		];
+
+		let resolved = resolve(&seeds).await;
+
+		assert!(resolved.addrs.len() >= 2);
+		assert_eq!(resolved.addrs[0], "127.0.0.2:7946".parse().unwrap());
+		assert!(resolved.addrs[1..]
+			.iter()
+			.all(|a| a.ip().is_loopback() && a.port() == 7946));
+		assert_eq!(resolved.errors.len(), 1);
+		assert_eq!(resolved.errors[0].0, "missing-port");
+	}
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::node::{Node, NodeState};
+
+const FILE_NAME: &str = "snapshot";
+const TMP_FILE_NAME: &str = "snapshot.tmp";
+
+#[derive(Debug, Error)]
+pub(crate) enum SnapshotError {
+	#[error("cannot access snapshot: {0}")]
+	Io(#[from] io::Error),
+	#[error("invalid snapshot at line {0}")]
+	Parse(usize),
+}
+
+/// The persisted state of a node, which allows it to survive restarts.
+///
+/// The snapshot is stored as a plain text file in the configured state directory:
+/// ```text
+/// incarnation 5
+/// peer 10.0.0.2:7946
+/// peer 10.0.0.3:7946
+/// ```
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+	/// The last incarnation number of the local node.
+	pub(crate) incarnation: u64,
+	/// The addresses of the last known peers which were not [NodeState::Dead] or [NodeState::Left].
+	pub(crate) peers: Vec<SocketAddr>,
+}
+
+impl Snapshot {
+	/// Creates a new snapshot from the incarnation of the local node and the currently known nodes.
+	/// The peers are sorted, so snapshots of the same members are equal.
+	pub(crate) fn new<'a, I>(incarnation: u64, nodes: I) -> Self
+	where
+		I: IntoIterator<Item = &'a Node>,
+	{
+		let mut peers: Vec<_> = nodes
+			.into_iter()
+			.filter(|n| matches!(n.state, NodeState::Alive(_) | NodeState::Suspect(_)))
+			.map(|n| n.addr)
+			.collect();
+		peers.sort_unstable();
+
+		Self { incarnation, peers }
+	}
+
+	/// Loads the snapshot from `dir`. Returns [None] if no snapshot has been stored yet.
+	pub(crate) fn load(dir: &Path) -> Result<Option<Self>, SnapshotError> {
+		let content = match fs::read_to_string(dir.join(FILE_NAME)) {
+			Ok(c) => c,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		let mut snapshot = Self::default();
+
+		for (i, line) in content.lines().enumerate() {
+			let mut parts = line.splitn(2, ' ');
+
+			match (parts.next(), parts.next()) {
+				(Some("incarnation"), Some(v)) => {
+					snapshot.incarnation = v.parse().map_err(|_| SnapshotError::Parse(i + 1))?;
+				}
+				(Some("peer"), Some(v)) => {
+					let addr = v.parse().map_err(|_| SnapshotError::Parse(i + 1))?;
+					snapshot.peers.push(addr);
+				}
+				(Some(""), None) => {}
+				_ => return Err(SnapshotError::Parse(i + 1)),
+			}
+		}
+
+		Ok(Some(snapshot))
+	}
+
+	/// Stores the snapshot in `dir`. The directory is created if it does not exist.
+	///
+	/// The snapshot is written to a temporary file first, which then replaces the old snapshot,
+	/// so a crash during a write never leaves a corrupted snapshot behind.
+	pub(crate) fn store(&self, dir: &Path) -> io::Result<()> {
+		fs::create_dir_all(dir)?;
+
+		let tmp = dir.join(TMP_FILE_NAME);
+		let mut file = fs::File::create(&tmp)?;
+
+		writeln!(file, "incarnation {}", self.incarnation)?;
+		for peer in self.peers.iter() {
+			writeln!(file, "peer {}", peer)?;
+		}
+		file.sync_all()?;
+
+		fs::rename(tmp, dir.join(FILE_NAME))
+	}
+
+	/// Returns the incarnation number to start with after a restart.
+	/// This is higher than the stored incarnation, so the node can refute its own death. A stored
+	/// incarnation of [u64::MAX] cannot be raised any further and is kept.
+	pub(crate) fn restart_incarnation(&self, configured: u64) -> u64 {
+		u64::max(configured, self.incarnation.saturating_add(1))
+	}
+
+	/// Returns the configured `host:port` seeds of a [JoinConfig](crate::JoinConfig) extended by the
+	/// stored peers. The configured seeds come first. Duplicates behind DNS names are removed when the
+	/// seeds get resolved.
+	pub(crate) fn seeds(&self, seeds: &[String]) -> Box<[String]> {
+		let mut result = seeds.to_vec();
+
+		for peer in self.peers.iter() {
+			let peer = peer.to_string();
+			if !result.contains(&peer) {
+				result.push(peer);
+			}
+		}
+
+		result.into_boxed_slice()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	fn addr(port: u16) -> SocketAddr {
+		format!("127.0.0.1:{}", port).parse().unwrap()
+	}
+
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("swimmers-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		dir
+	}
+
+	#[test]
+	fn store_and_load() {
+		let dir = temp_dir("store-and-load");
+
+		assert_eq!(Snapshot::load(&dir).unwrap(), None);
+
+		let nodes = [
+			Node {
+				addr: addr(1),
+				state: NodeState::Alive(1),
+				metadata: None,
+				rtt: None,
+			},
+			Node {
+				addr: addr(2),
+				state: NodeState::Dead(1),
+				metadata: None,
+				rtt: None,
+			},
+			Node {
+				addr: addr(3),
+				state: NodeState::Suspect(4),
+				metadata: None,
+				rtt: None,
+			},
+		];
+
+		let snapshot = Snapshot::new(5, nodes.iter());
+		assert_eq!(snapshot.peers, vec![addr(1), addr(3)]);
+
+		snapshot.store(&dir).unwrap();
+		assert_eq!(Snapshot::load(&dir).unwrap(), Some(snapshot));
+
+		fs::write(dir.join(FILE_NAME), "incarnation 5\npeer nope\n").unwrap();
+		assert!(matches!(Snapshot::load(&dir), Err(SnapshotError::Parse(2))));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn restart() {
+		let snapshot = Snapshot {
+			incarnation: 5,
+			peers: vec![addr(1), addr(2)],
+		};
+
+		assert_eq!(snapshot.restart_incarnation(0), 6);
+		assert_eq!(snapshot.restart_incarnation(10), 10);
+
+		let seeds = snapshot.seeds(&["seed.local:7946".to_string(), addr(2).to_string()]);
+		assert_eq!(
+			&*seeds,
+			&[
+				"seed.local:7946".to_string(),
+				addr(2).to_string(),
+				addr(1).to_string()
+			]
+		);
+	}
+
+	#[test]
+	fn restart_at_max_incarnation() {
+		let snapshot = Snapshot {
+			incarnation: u64::MAX,
+			peers: Vec::new(),
+		};
+
+		assert_eq!(snapshot.restart_incarnation(0), u64::MAX);
+	}
+}
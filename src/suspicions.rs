@@ -4,11 +4,66 @@ use std::collections::{HashMap, HashSet};
 
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::clock::Clock;
 
 #[derive(Debug)]
 pub(crate) struct Suspicion {
 	pub(crate) incarnation: u64,
 	pub(crate) suspectors: HashSet<SocketAddr>,
+	/// The time the suspicion started.
+	pub(crate) started: Instant,
+	/// The suspectors in the order their suspicions were received, with the time passed since
+	/// [Suspicion::started].
+	pub(crate) confirmations: Vec<Suspector>,
+}
+
+impl Suspicion {
+	fn new(incarnation: u64, suspector: SocketAddr, now: Instant) -> Self {
+		let mut suspectors = HashSet::with_capacity(1);
+		suspectors.insert(suspector);
+
+		Self {
+			incarnation,
+			suspectors,
+			started: now,
+			confirmations: vec![Suspector {
+				addr: suspector,
+				after: Duration::from_nanos(0),
+			}],
+		}
+	}
+}
+
+/// A node which suspected another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suspector {
+	pub addr: SocketAddr,
+	/// The time between the start of the suspicion and the receipt of this suspector's suspicion.
+	pub after: Duration,
+}
+
+/// The details of an active suspicion.
+#[derive(Debug, Clone)]
+pub struct SuspicionInfo {
+	/// The suspected node.
+	pub addr: SocketAddr,
+	/// The suspected incarnation of the node.
+	pub incarnation: u64,
+	/// Every distinct suspector in the order their suspicions were received.
+	pub suspectors: Vec<Suspector>,
+	/// The time the suspicion started.
+	pub started: Instant,
+	/// The current timeout of the suspicion, which shrinks with each new suspector.
+	/// [None] if no timer is running for the suspicion.
+	pub timeout: Option<Duration>,
+	/// The time the node will be declared dead unless it refutes the suspicion.
+	/// [None] if no timer is running for the suspicion.
+	pub expires: Option<Instant>,
 }
 
 pub(crate) enum SuspicionResult {
@@ -29,14 +84,20 @@ impl SuspicionResult {
 	}
 }
 
-#[derive(Debug, Default)]
+/// The active suspicions about other nodes. The start of each suspicion is read from the [Clock]
+/// which drives the suspicion timers.
+#[derive(Debug)]
 pub(crate) struct Suspicions {
 	suspicions: HashMap<SocketAddr, Suspicion>,
+	clock: Arc<dyn Clock>,
 }
 
 impl Suspicions {
-	pub(crate) fn new() -> Self {
-		Self::default()
+	pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+		Self {
+			suspicions: HashMap::new(),
+			clock,
+		}
 	}
 
 	pub(crate) fn suspect(
@@ -45,16 +106,11 @@ impl Suspicions {
 		incarnation: u64,
 		suspector: SocketAddr,
 	) -> Option<SuspicionResult> {
+		let now = self.clock.now();
+
 		let result = match self.suspicions.entry(addr) {
 			Entry::Vacant(entry) => {
-				let mut suspectors = HashSet::with_capacity(1);
-				suspectors.insert(suspector);
-
-				let suspicion = Suspicion {
-					incarnation,
-					suspectors,
-				};
-				entry.insert(suspicion);
+				entry.insert(Suspicion::new(incarnation, suspector, now));
 
 				SuspicionResult::New
 			}
@@ -63,10 +119,7 @@ impl Suspicions {
 				match incarnation.cmp(&suspicion.incarnation) {
 					Ordering::Less => return None,
 					Ordering::Greater => {
-						suspicion.suspectors.clear();
-						suspicion.suspectors.insert(suspector);
-
-						suspicion.incarnation = incarnation;
+						*suspicion = Suspicion::new(incarnation, suspector, now);
 
 						SuspicionResult::Reset
 					}
@@ -75,6 +128,11 @@ impl Suspicions {
 							return None;
 						}
 
+						suspicion.confirmations.push(Suspector {
+							addr: suspector,
+							after: now.saturating_duration_since(suspicion.started),
+						});
+
 						let count = suspicion.suspectors.len();
 						let count = NonZeroUsize::new(count).unwrap();
 
@@ -92,6 +150,29 @@ impl Suspicions {
 		self.suspicions.get(addr)
 	}
 
+	/// Returns the details of every active suspicion. `timeout` returns the current timeout and expiry
+	/// time of a suspicion, see [Scheduler::suspicion_timeout](crate::scheduler::Scheduler::suspicion_timeout).
+	pub(crate) fn suspicions<F>(&self, mut timeout: F) -> Vec<SuspicionInfo>
+	where
+		F: FnMut(&SocketAddr) -> Option<(Duration, Instant)>,
+	{
+		self.suspicions
+			.iter()
+			.map(|(addr, s)| {
+				let timeout = timeout(addr);
+
+				SuspicionInfo {
+					addr: *addr,
+					incarnation: s.incarnation,
+					suspectors: s.confirmations.clone(),
+					started: s.started,
+					timeout: timeout.map(|(d, _)| d),
+					expires: timeout.map(|(_, at)| at),
+				}
+			})
+			.collect()
+	}
+
 	#[inline]
 	pub(crate) fn remove(&mut self, addr: &SocketAddr) -> Option<Suspicion> {
 		self.suspicions.remove(addr)
@@ -101,6 +182,7 @@ impl Suspicions {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::clock::{ManualClock, TokioClock};
 
 	#[test]
 	fn suspect() {
@@ -108,7 +190,7 @@ mod tests {
 			format!("127.0.0.1:{}", port).parse().unwrap()
 		}
 
-		let mut s = Suspicions::new();
+		let mut s = Suspicions::new(Arc::new(TokioClock));
 
 		let result = s.suspect(addr(1), 1, addr(1)).unwrap();
 		assert!(matches!(result, SuspicionResult::New));
@@ -137,4 +219,52 @@ mod tests {
 		let result = s.remove(&addr(1));
 		assert!(result.is_none());
 	}
+
+	#[test]
+	fn suspicions() {
+		fn addr(port: u16) -> SocketAddr {
+			format!("127.0.0.1:{}", port).parse().unwrap()
+		}
+
+		// far away from the time of the runtime, so a mix of both would show.
+		let started = Instant::now() + Duration::from_secs(3600);
+		let clock = Arc::new(ManualClock::new(started));
+		let mut s = Suspicions::new(clock.clone());
+
+		s.suspect(addr(1), 1, addr(2));
+		clock.advance(Duration::from_millis(300));
+		s.suspect(addr(1), 1, addr(3));
+		s.suspect(addr(1), 1, addr(2));
+
+		let expires = started + Duration::from_secs(5);
+		let info = s.suspicions(|a| {
+			assert_eq!(*a, addr(1));
+			Some((Duration::from_secs(5), expires))
+		});
+
+		assert_eq!(info.len(), 1);
+		assert_eq!(info[0].addr, addr(1));
+		assert_eq!(info[0].started, started);
+		assert_eq!(info[0].timeout, Some(Duration::from_secs(5)));
+		assert_eq!(info[0].expires, Some(expires));
+		assert_eq!(
+			info[0].suspectors,
+			vec![
+				Suspector {
+					addr: addr(2),
+					after: Duration::from_nanos(0),
+				},
+				Suspector {
+					addr: addr(3),
+					after: Duration::from_millis(300),
+				},
+			]
+		);
+
+		s.suspect(addr(1), 2, addr(4));
+		let info = s.suspicions(|_| None);
+		assert_eq!(info[0].suspectors.len(), 1);
+		assert_eq!(info[0].started, started + Duration::from_millis(300));
+		assert!(info[0].expires.is_none());
+	}
 }